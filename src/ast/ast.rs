@@ -4,13 +4,17 @@
 
 #[crate_type = "lib"];
 
+pub use atom::{Atom, AtomTable};
 pub use node_type::NodeType;
+pub mod atom;
 pub mod node_type;
 
 // Node structures.
 
 pub struct Node<T> {
     loc: SourceLocation,
+    // Byte offsets of the start and the end of the node in the source.
+    range: Option<(uint, uint)>,
     body: T,
 }
 
@@ -236,6 +240,7 @@ pub enum Expression {
     ExprMember(~Node<MemberExpression>),
     // From Miscellaneous.
     ExprIdentifier(~Node<Identifier>),
+    ExprLiteral(~Node<Literal>),
 }
 
 pub struct ThisExpression;
@@ -342,16 +347,17 @@ pub struct CatchClause {
 // Miscellaneous
 
 pub struct Identifier {
-    name: ~str,
+    name: Atom,
 }
 
 pub enum LiteralValue {
-    LV_String(~str),
+    LV_String(Atom),
     LV_Boolean(bool),
     LV_Null,
     // TODO
     LV_Number(f64),
-    LV_RegExp(~str),
+    // The pattern and the flags.
+    LV_RegExp(Atom, Atom),
 }
 
 pub struct Literal {
@@ -383,6 +389,7 @@ pub enum BinaryOperator {
     BO_PLUS, // "+"
     BO_MINUS, // "-"
     BO_MUL, // "*"
+    BO_EXP, // "**"
     BO_DIV, // "/"
     BO_MOD, // "%"
     BO_BITWISE_OR, // "|"
@@ -403,6 +410,7 @@ pub enum AssignmentOperator {
     AO_PLUS, // "+="
     AO_MINUS, // "-="
     AO_MUL, // "*="
+    AO_EXP, // "**="
     AO_DIV, // "/="
     AO_MOD, // "%="
     AO_LSH, // "<<="
@@ -411,6 +419,9 @@ pub enum AssignmentOperator {
     AO_BITWISE_OR, // "|="
     AO_BITWISE_XOR, // "^="
     AO_BITWISE_AND, // "&="
+    AO_OR, // "||="
+    AO_AND, // "&&="
+    AO_NULLISH, // "??="
 }
 
 pub enum UpdateOperator {
@@ -421,9 +432,10 @@ pub enum UpdateOperator {
 
 
 impl<T> Node<T> {
-    pub fn new(loc: SourceLocation, t: T) -> Node<T> {
+    pub fn new(loc: SourceLocation, range: Option<(uint, uint)>, t: T) -> Node<T> {
         Node {
             loc: loc,
+            range: range,
             body: t,
         }
     }
@@ -455,6 +467,243 @@ impl Program {
     }
 }
 
+impl FunctionBody {
+    pub fn new(id: Option<Node<Identifier>>, params: ~[Pattern], defaults: ~[Expression],
+               rest: Option<Node<Identifier>>, body: Either<Node<BlockStatement>, Expression>,
+               generator: bool, expression: bool) -> FunctionBody {
+        FunctionBody {
+            id: id,
+            params: params,
+            defaults: defaults,
+            rest: rest,
+            body: body,
+            generator: generator,
+            expression: expression,
+        }
+    }
+}
+
+impl BlockStatement {
+    pub fn new(body: ~[Statement]) -> BlockStatement {
+        BlockStatement {
+            body: body,
+        }
+    }
+}
+
+impl ExpressionStatement {
+    pub fn new(expression: Expression) -> ExpressionStatement {
+        ExpressionStatement {
+            expression: expression,
+        }
+    }
+}
+
+impl IfStatement {
+    pub fn new(test: Expression, consquent: Statement, alternate: Option<Statement>) -> IfStatement {
+        IfStatement {
+            test: test,
+            consquent: consquent,
+            alternate: alternate,
+        }
+    }
+}
+
+impl LabledStatement {
+    pub fn new(lable: Node<Identifier>, body: Statement) -> LabledStatement {
+        LabledStatement {
+            lable: lable,
+            body: body,
+        }
+    }
+}
+
+impl BreakStatement {
+    pub fn new(lable: Option<Node<Identifier>>) -> BreakStatement {
+        BreakStatement {
+            lable: lable,
+        }
+    }
+}
+
+impl ContinueStatement {
+    pub fn new(lable: Option<Node<Identifier>>) -> ContinueStatement {
+        ContinueStatement {
+            lable: lable,
+        }
+    }
+}
+
+impl SwitchStatement {
+    pub fn new(discriminant: Expression, cases: ~[Node<SwitchCase>], lexical: bool) -> SwitchStatement {
+        SwitchStatement {
+            discriminant: discriminant,
+            cases: cases,
+            lexical: lexical,
+        }
+    }
+}
+
+impl ReturnStatement {
+    pub fn new(argument: Option<Expression>) -> ReturnStatement {
+        ReturnStatement {
+            argument: argument,
+        }
+    }
+}
+
+impl WhileStatement {
+    pub fn new(test: Expression, body: Statement) -> WhileStatement {
+        WhileStatement {
+            test: test,
+            body: body,
+        }
+    }
+}
+
+impl DoWhileStatement {
+    pub fn new(body: Statement, test: Expression) -> DoWhileStatement {
+        DoWhileStatement {
+            body: body,
+            test: test,
+        }
+    }
+}
+
+impl ForStatement {
+    pub fn new(init: Option<Either<Node<VariableDeclaration>, Expression>>, test: Option<Expression>,
+               update: Option<Expression>, body: Statement) -> ForStatement {
+        ForStatement {
+            init: init,
+            test: test,
+            update: update,
+            body: body,
+        }
+    }
+}
+
+impl ForInStatement {
+    pub fn new(left: Either<Node<VariableDeclaration>, Expression>, right: Expression,
+               body: Statement) -> ForInStatement {
+        ForInStatement {
+            left: left,
+            right: right,
+            body: body,
+            each: false,
+        }
+    }
+}
+
+impl VariableDeclaration {
+    pub fn new(declarations: ~[Node<VariableDeclarator>], kind: Node<DeclarationKind>) -> VariableDeclaration {
+        VariableDeclaration {
+            declarations: declarations,
+            kind: kind,
+        }
+    }
+}
+
+impl VariableDeclarator {
+    pub fn new(id: Pattern, init: Option<Expression>) -> VariableDeclarator {
+        VariableDeclarator {
+            id: id,
+            init: init,
+        }
+    }
+}
+
+impl ArrayExpression {
+    pub fn new(elements: ~[Option<Expression>]) -> ArrayExpression {
+        ArrayExpression {
+            elements: elements,
+        }
+    }
+}
+
+impl ObjectExpressionProperty {
+    pub fn new(key: Either<Node<Literal>, Node<Identifier>>, value: Expression,
+               kind: ObjectExpressionPropertyKind) -> ObjectExpressionProperty {
+        ObjectExpressionProperty {
+            key: key,
+            value: value,
+            kind: kind,
+        }
+    }
+}
+
+impl ObjectExpression {
+    pub fn new(properties: ~[ObjectExpressionProperty]) -> ObjectExpression {
+        ObjectExpression {
+            properties: properties,
+        }
+    }
+}
+
+impl FunctionExpression {
+    pub fn new(function: Node<FunctionBody>) -> FunctionExpression {
+        FunctionExpression {
+            function: function,
+        }
+    }
+}
+
+impl SequenceExpression {
+    pub fn new(expressions: ~[Expression]) -> SequenceExpression {
+        SequenceExpression {
+            expression: expressions,
+        }
+    }
+}
+
+impl AssignmentExpression {
+    pub fn new(op: AssignmentOperator, left: Expression, right: Expression) -> AssignmentExpression {
+        AssignmentExpression {
+            operator: op,
+            left: left,
+            right: right,
+        }
+    }
+}
+
+impl CondionalExpression {
+    pub fn new(test: Expression, consequent: Expression, alternate: Expression) -> CondionalExpression {
+        CondionalExpression {
+            test: test,
+            alternate: alternate,
+            consequent: consequent,
+        }
+    }
+}
+
+impl NewExpression {
+    pub fn new(callee: Expression, arguments: ~[Expression]) -> NewExpression {
+        NewExpression {
+            callee: callee,
+            arguments: arguments,
+        }
+    }
+}
+
+impl CallExpression {
+    pub fn new(callee: Expression, arguments: ~[Expression]) -> CallExpression {
+        CallExpression {
+            callee: callee,
+            arguments: arguments,
+        }
+    }
+}
+
+impl MemberExpression {
+    pub fn new(object: Expression, property: Either<Node<Identifier>, Expression>,
+               computed: bool) -> MemberExpression {
+        MemberExpression {
+            object: object,
+            property: property,
+            computed: computed,
+        }
+    }
+}
+
 impl UnaryExpression {
     pub fn new(op: UnaryOperator, arg: Expression, prefix: bool) -> UnaryExpression {
         UnaryExpression {
@@ -494,3 +743,28 @@ impl LogicalExpression {
         }
     }
 }
+
+impl Identifier {
+    pub fn new(name: Atom) -> Identifier {
+        Identifier {
+            name: name,
+        }
+    }
+}
+
+impl Literal {
+    pub fn new(value: LiteralValue) -> Literal {
+        Literal {
+            value: value,
+        }
+    }
+}
+
+impl SwitchCase {
+    pub fn new(test: Option<Expression>, consequent: ~[Statement]) -> SwitchCase {
+        SwitchCase {
+            test: test,
+            consequent: consequent,
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::hash::Hash;
+use std::uint;
+use std::vec;
+
+// A free bucket of an atom table.
+static EMPTY: uint = uint::max_value;
+
+// The number of buckets of a new atom table. It is a power of two.
+static INITIAL_BUCKETS: uint = 64;
+
+// An interned string. Atoms from the same table are equal if and only if
+// their strings are equal, so they compare and hash in O(1).
+#[deriving(Clone, Eq, IterBytes)]
+pub struct Atom {
+    priv id: uint,
+}
+
+// The string of an atom. It points into the source when it can, and is owned
+// only when it is not a part of the source, such as a name with escapes.
+enum AtomStr<'a> {
+    Borrowed(&'a str),
+    Owned(~str),
+}
+
+// The table of the strings of atoms. It lives as long as the source.
+//
+// Each string is stored once, in `strs`. The buckets hold atom ids and are
+// probed linearly, so a lookup compares strings of the same hash only.
+pub struct AtomTable<'a> {
+    priv strs: ~[AtomStr<'a>],
+    // The hashes of `strs`, kept to compare and rehash cheaply.
+    priv hashes: ~[u64],
+    // The number of buckets is a power of two, and at most half of them are
+    // taken.
+    priv buckets: ~[uint],
+}
+
+impl Atom {
+    // The index of the atom in its table.
+    #[inline]
+    pub fn id(&self) -> uint {
+        self.id
+    }
+}
+
+impl<'a> AtomStr<'a> {
+    #[inline]
+    fn as_slice<'b>(&'b self) -> &'b str {
+        match *self {
+            Borrowed(s) => s,
+            Owned(ref s) => s.as_slice(),
+        }
+    }
+}
+
+impl<'a> AtomTable<'a> {
+    pub fn new() -> AtomTable<'a> {
+        AtomTable {
+            strs: ~[],
+            hashes: ~[],
+            buckets: vec::from_elem(INITIAL_BUCKETS, EMPTY),
+        }
+    }
+
+    // Return the atom of the string, copying it into the table if it is new.
+    pub fn intern(&mut self, s: &str) -> Atom {
+        let hash = s.hash();
+        match self.probe(s, hash) {
+            Ok(atom) => atom,
+            Err(bucket) => self.insert(Owned(s.to_owned()), hash, bucket)
+        }
+    }
+
+    // Return the atom of the string, keeping the reference if it is new.
+    pub fn intern_borrowed(&mut self, s: &'a str) -> Atom {
+        let hash = s.hash();
+        match self.probe(s, hash) {
+            Ok(atom) => atom,
+            Err(bucket) => self.insert(Borrowed(s), hash, bucket)
+        }
+    }
+
+    // Look the string up. Return its atom, or the free bucket to put it in.
+    fn probe(&self, s: &str, hash: u64) -> Result<Atom, uint> {
+        let mask = self.buckets.len() - 1;
+        let mut i = hash as uint & mask;
+        loop {
+            let id = self.buckets[i];
+            if id == EMPTY {
+                return Err(i);
+            }
+            if self.hashes[id] == hash && self.strs[id].as_slice() == s {
+                return Ok(Atom { id: id });
+            }
+            i = (i + 1) & mask;
+        }
+    }
+
+    fn insert(&mut self, s: AtomStr<'a>, hash: u64, bucket: uint) -> Atom {
+        let atom = Atom { id: self.strs.len() };
+        self.strs.push(s);
+        self.hashes.push(hash);
+        self.buckets[bucket] = atom.id;
+        if self.strs.len() * 2 > self.buckets.len() {
+            self.grow();
+        }
+        atom
+    }
+
+    // Double the buckets and put the atoms back by their hashes.
+    fn grow(&mut self) {
+        let len = self.buckets.len() * 2;
+        let mut buckets = vec::from_elem(len, EMPTY);
+        for (id, &hash) in self.hashes.iter().enumerate() {
+            let mut i = hash as uint & (len - 1);
+            while buckets[i] != EMPTY {
+                i = (i + 1) & (len - 1);
+            }
+            buckets[i] = id;
+        }
+        self.buckets = buckets;
+    }
+
+    // Return the string of the atom. The atom must come from this table.
+    #[inline]
+    pub fn get<'b>(&'b self, atom: Atom) -> &'b str {
+        self.strs[atom.id].as_slice()
+    }
+
+    // Return the atom of the string if it is interned already.
+    pub fn find(&self, s: &str) -> Option<Atom> {
+        match self.probe(s, s.hash()) {
+            Ok(atom) => Some(atom),
+            Err(_) => None
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> uint {
+        self.strs.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AtomTable;
+    use std::vec;
+
+    #[test]
+    fn intern() {
+        let mut atoms = AtomTable::new();
+        let a = atoms.intern("foo");
+        let b = atoms.intern("bar");
+        assert!(a != b);
+        assert_eq!(atoms.intern("foo"), a);
+        assert_eq!(atoms.get(a), "foo");
+        assert_eq!(atoms.get(b), "bar");
+        assert_eq!(atoms.find("bar"), Some(b));
+        assert_eq!(atoms.find("baz"), None);
+        assert_eq!(atoms.len(), 2);
+    }
+
+    #[test]
+    fn intern_borrowed() {
+        let src = ~"foo bar";
+        let mut atoms = AtomTable::new();
+        let a = atoms.intern_borrowed(src.slice(0, 3));
+        assert_eq!(atoms.intern("foo"), a);
+        assert_eq!(atoms.intern_borrowed(src.slice(4, 7)), atoms.intern("bar"));
+        assert_eq!(atoms.get(a).as_ptr(), src.as_ptr());
+    }
+
+    #[test]
+    fn grow() {
+        let mut atoms = AtomTable::new();
+        let names = vec::from_fn(1000, |i| format!("a{}", i));
+        let ids = names.map(|name| atoms.intern(*name));
+        assert_eq!(atoms.len(), 1000);
+        for (name, &atom) in names.iter().zip(ids.iter()) {
+            assert_eq!(atoms.find(*name), Some(atom));
+            assert_eq!(atoms.get(atom), name.as_slice());
+        }
+    }
+}
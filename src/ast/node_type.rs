@@ -23,7 +23,7 @@ use super::Expression;
 use super::{ExprThis, ExprArray, ExprObject, ExprFunction};
 use super::{ExprArrow, ExprSequence, ExprUnary, ExprBinary};
 use super::{ExprAssignment, ExprUpdate, ExprLogical, ExprConditional};
-use super::{ExprNew, ExprCall, ExprMember, ExprIdentifier};
+use super::{ExprNew, ExprCall, ExprMember, ExprIdentifier, ExprLiteral};
 use super::{ThisExpression, ArrayExpression, ObjectExpression, FunctionExpression};
 use super::{ArrowExpression, SequenceExpression, UnaryExpression, BinaryExpression};
 use super::{AssignmentExpression, UpdateExpression, LogicalExpression, CondionalExpression};
@@ -188,6 +188,7 @@ impl NodeType for Expression {
             ExprCall(ref v) => v.type_name(),
             ExprMember(ref v) => v.type_name(),
             ExprIdentifier(ref v) => v.type_name(),
+            ExprLiteral(ref v) => v.type_name(),
         }
     }
 }
@@ -6,17 +6,22 @@
 extern mod grinder;
 
 use std::io;
+use std::os;
 
-use grinder::lexer::Lexer;
-
+// Print the tokens of the source given as the first argument.
 fn main() {
-    let src = ~"var a=1; var b=2; a+b;";
-    let mut lexer = Lexer::new(src);
-    loop {
-        match lexer.next_token() {
-            Some(token) => io::println(format!("{:?}", token)),
-            None => break
+    let args = os::args();
+    let src = if args.len() > 1 { args[1].as_slice() } else { "var a=1; var b=2; a+b;" };
+    let options = grinder::TokenizeOptions { comments: true, eof: false, module: false };
+    match grinder::tokenize(src, options) {
+        Ok(tokens) => {
+            for token in tokens.iter() {
+                for comment in token.comments.iter() {
+                    io::println(format!("{} {:?}", comment.type_name(), comment.text(src)));
+                }
+                io::println(format!("{} {:?}", token.type_name(), token.value(src)));
+            }
         }
+        Err(err) => io::println(err.to_str())
     }
 }
-
@@ -0,0 +1,105 @@
+use reader::Location;
+use token;
+
+#[deriving(Clone, Eq)]
+pub enum LexMessageType {
+    LMT_None,
+    LMT_Illegal,
+}
+
+#[deriving(Clone, Eq)]
+pub enum LexMessage {
+    UnexpectedToken,
+    UnexpectedNumber,
+    UnexpectedString,
+    UnexpectedEOS,
+    InvalidHexEscape,
+    InvalidUnicodeEscape,
+    EscapedKeyword,
+    UnterminatedComment,
+    UnterminatedRegExp,
+    InvalidRegExpFlags,
+    UnterminatedTemplate,
+    InvalidLHSInAssignment,
+    InvalidLHSInForIn,
+    IllegalReturn,
+    IllegalBreak,
+    IllegalContinue,
+    UnknownLabel,
+    Redeclaration,
+    MultipleDefaultsInSwitch,
+    NotImplemented,
+}
+
+// An error found while lexing or parsing a source.
+#[deriving(Clone, Eq)]
+pub struct SyntaxError {
+    msg: LexMessage,
+    mtype: LexMessageType,
+    // Where the error is found.
+    loc: Location,
+    // The offending token. None if the source could not be lexed into one.
+    token: Option<token::Token>,
+}
+
+impl ToStr for LexMessageType {
+    #[inline]
+    fn to_str(&self) -> ~str {
+        match *self {
+            LMT_None => ~"",
+            LMT_Illegal => ~"ILLEGAL",
+        }
+    }
+}
+
+impl ToStr for LexMessage {
+    fn to_str(&self) -> ~str {
+        match *self {
+            UnexpectedToken => ~"Unexpected token",
+            UnexpectedNumber => ~"Unexpected number",
+            UnexpectedString => ~"Unexpected string",
+            UnexpectedEOS => ~"Unexpected end of input",
+            InvalidHexEscape => ~"Invalid hexadecimal escape sequence",
+            InvalidUnicodeEscape => ~"Invalid Unicode escape sequence",
+            EscapedKeyword => ~"Keyword must not contain escaped characters",
+            UnterminatedComment => ~"Unterminated comment",
+            UnterminatedRegExp => ~"Invalid regular expression: missing /",
+            InvalidRegExpFlags => ~"Invalid regular expression flags",
+            UnterminatedTemplate => ~"Unterminated template literal",
+            InvalidLHSInAssignment => ~"Invalid left-hand side in assignment",
+            InvalidLHSInForIn => ~"Invalid left-hand side in for-in",
+            IllegalReturn => ~"Illegal return statement",
+            IllegalBreak => ~"Illegal break statement",
+            IllegalContinue => ~"Illegal continue statement",
+            UnknownLabel => ~"Undefined label",
+            Redeclaration => ~"Label has already been declared",
+            MultipleDefaultsInSwitch => ~"More than one default clause in switch statement",
+            NotImplemented => ~"Not implemented",
+        }
+    }
+}
+
+impl SyntaxError {
+    pub fn new(msg: LexMessage, mtype: LexMessageType, loc: Location,
+               token: Option<token::Token>) -> SyntaxError {
+        SyntaxError {
+            msg: msg,
+            mtype: mtype,
+            loc: loc,
+            token: token,
+        }
+    }
+}
+
+impl ToStr for SyntaxError {
+    fn to_str(&self) -> ~str {
+        let mtype = self.mtype.to_str();
+        if mtype.is_empty() {
+            format!("SyntaxError: {} at row:{}, col:{}",
+                self.msg.to_str(), self.loc.row, self.loc.col)
+        } else {
+            format!("SyntaxError: {} {} at row:{}, col:{}",
+                self.msg.to_str(), mtype, self.loc.row, self.loc.col)
+        }
+    }
+}
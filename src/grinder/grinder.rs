@@ -4,10 +4,99 @@
 
 #[crate_type = "lib"];
 
+#[feature(macro_rules)];
+
 extern mod ast;
+#[cfg(test)]
+extern mod extra;
+
+// Return the value of a Result, or return the error from the function.
+macro_rules! if_ok (
+    ($e:expr) => (
+        match $e {
+            Ok(v) => v,
+            Err(e) => return Err(e)
+        }
+    )
+)
 
+pub mod error;
 pub mod reader;
 pub mod lexer;
+pub mod line_index;
 pub mod parser;
-mod token;
+pub mod token;
+mod num;
 mod util;
+
+// Options of `tokenize`.
+pub struct TokenizeOptions {
+    // Whether comments are kept in the tokens following them.
+    comments: bool,
+    // Whether the EOF token ends the result.
+    eof: bool,
+    // Whether the source is module code rather than a script.
+    module: bool,
+}
+
+impl TokenizeOptions {
+    pub fn default() -> TokenizeOptions {
+        TokenizeOptions {
+            comments: false,
+            eof: false,
+            module: false,
+        }
+    }
+}
+
+// Lex the whole source into tokens.
+// `token::TokenInfo::type_name` and `value` give esprima's `tokens` output.
+pub fn tokenize(src: &str, options: TokenizeOptions) -> Result<~[token::TokenInfo], error::SyntaxError> {
+    let lexer_options = lexer::LexerOptions {
+        comments: options.comments,
+        module: options.module,
+    };
+    let mut lexer = lexer::Lexer::new_with_options(src, lexer_options);
+    let mut tokens = ~[];
+    loop {
+        match if_ok!(lexer.next_token()) {
+            Some(info) => tokens.push(info),
+            None => break
+        }
+    }
+    if options.eof {
+        tokens.push(lexer.eof_token());
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tokenize, TokenizeOptions};
+    use token;
+
+    #[test]
+    fn tokenize_esprima() {
+        let src = "var answer = 42; // life\nif (x === true) y = /re/g";
+        let tokens = tokenize(src, TokenizeOptions::default()).unwrap();
+        let out: ~[(&str, &str)] = tokens.iter().map(|t| (t.type_name(), t.value(src))).collect();
+        assert_eq!(out, ~[("Keyword", "var"), ("Identifier", "answer"), ("Punctuator", "="),
+                          ("Numeric", "42"), ("Punctuator", ";"), ("Keyword", "if"), ("Punctuator", "("),
+                          ("Identifier", "x"), ("Punctuator", "==="), ("Boolean", "true"),
+                          ("Punctuator", ")"), ("Identifier", "y"), ("Punctuator", "="),
+                          ("RegularExpression", "/re/g")]);
+    }
+
+    #[test]
+    fn tokenize_options() {
+        let options = TokenizeOptions { comments: true, eof: true, module: false };
+        let src = "a /* b */";
+        let tokens = tokenize(src, options).unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].tok, token::EOF);
+        assert_eq!(tokens[1].comments[0].text(src), " b ");
+        assert_eq!(tokens[1].comments[0].type_name(), "BlockComment");
+
+        assert!(tokenize("'a", TokenizeOptions::default()).is_err());
+    }
+}
@@ -1,230 +1,718 @@
 use std::char;
 
-use reader::Reader;
+use ast::AtomTable;
+
+use error::{SyntaxError, LexMessage, LexMessageType, LMT_None, LMT_Illegal};
+use error::{UnexpectedToken, UnexpectedNumber, InvalidHexEscape, InvalidUnicodeEscape};
+use error::{EscapedKeyword, UnterminatedRegExp, InvalidRegExpFlags, UnterminatedTemplate};
+use line_index::LineIndex;
+use num;
+use reader::{Reader, ReaderState, Trivia};
 use token;
 use util;
 
-#[deriving(Clone, Eq)]
-pub enum LexMessageType {
-    LMT_None,
-    LMT_Illegal,
+pub struct LexerOptions {
+    // Whether comments are kept in the tokens following them.
+    comments: bool,
+    // Whether the source is module code. Hashbang and HTML-like comments are
+    // allowed only in scripts.
+    module: bool,
+}
+
+pub struct Lexer<'a> {
+    priv reader: Reader<'a>,
+    priv options: LexerOptions,
+    // The names and the literal values in the tokens.
+    priv atoms: AtomTable<'a>,
+    // The last token returned. It decides whether a '/' starts a regular
+    // expression or is a division operator.
+    priv prev: Option<token::Token>,
+    // What follows the last token. It belongs to the EOF token.
+    priv trailing: Trivia,
+    // Whether the iteration is over, by the end of the source or an error.
+    priv done: bool,
+    // The open braces. True for the "${" of a template substitution, whose
+    // '}' resumes the template.
+    priv braces: ~[bool],
 }
 
-#[deriving(Clone, Eq)]
-pub enum LexMessage {
-    UnexpectedToken,
-    UnexpectedNumber,
-    UnexpectedString,
-    NotImplemented,
+// A saved state of a lexer, to rewind to for speculative parsing.
+#[deriving(Clone)]
+pub struct Checkpoint {
+    priv reader: ReaderState,
+    priv prev: Option<token::Token>,
+    priv trailing: Trivia,
+    priv done: bool,
+    priv braces: ~[bool],
 }
 
-impl ToStr for LexMessageType {
-    #[inline]
-    fn to_str(&self) -> ~str {
-        match *self {
-            LMT_None => ~"",
-            LMT_Illegal => ~"ILLEGAL",
+impl LexerOptions {
+    pub fn default() -> LexerOptions {
+        LexerOptions {
+            comments: false,
+            module: false,
         }
     }
 }
 
-pub struct Lexer {
-    priv reader: Reader,
-}
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Lexer<'a> {
+        Lexer::new_with_options(src, LexerOptions::default())
+    }
 
-impl Lexer {
-    pub fn new(src: ~str) -> Lexer {
+    pub fn new_with_options(src: &'a str, options: LexerOptions) -> Lexer<'a> {
         Lexer {
             reader: Reader::new(src),
+            options: options,
+            atoms: AtomTable::new(),
+            prev: None,
+            trailing: Trivia { newline: false, comments: ~[] },
+            done: false,
+            braces: ~[],
         }
     }
 
-    pub fn next_token(&mut self) -> Option<token::Token> {
+    // Return the next token, or None at the end of the source.
+    pub fn next_token(&mut self) -> Result<Option<token::TokenInfo>, SyntaxError> {
+        let trivia = if_ok!(self.reader.consume_whitespace_and_comments(self.options.comments,
+                                                                        !self.options.module));
         if !self.reader.is_eof() {
-            let token = next_token(&mut self.reader);
-            Some(token)
+            let start = self.reader.curr_loc();
+            let token = if self.reader.is_curr('}') && self.braces.last_opt() == Some(&true) {
+                if_ok!(scan_template(&mut self.reader, &mut self.atoms))
+            } else {
+                let regexp_allowed = is_regexp_allowed(&self.prev);
+                if_ok!(next_token(&mut self.reader, &mut self.atoms, regexp_allowed))
+            };
+            match token {
+                token::LBRACE => self.braces.push(false),
+                token::RBRACE => { self.braces.pop_opt(); }
+                token::TEMPLATE(token::TemplateHead, _, _) => self.braces.push(true),
+                token::TEMPLATE(token::TemplateTail, _, _) => { self.braces.pop_opt(); }
+                _ => ()
+            }
+            let span = token::Span::new(start, self.reader.curr_loc());
+            self.prev = Some(token.clone());
+            Ok(Some(token::TokenInfo::new(token, span, trivia.comments, trivia.newline)))
         } else {
-            None
+            if trivia.newline || !trivia.comments.is_empty() {
+                self.trailing = trivia;
+            }
+            Ok(None)
         }
     }
 
+    // Lex a '/' or '/=' token again as a regular expression, and update it.
+    // Whether a '/' starts one is decided by the token before it, which is
+    // not always right: after the ')' of an "if" condition or the '}' of a
+    // block, a regular expression is expected. The parser knows and calls
+    // this. The token must be the last one returned.
+    pub fn rescan_regexp(&mut self, info: &mut token::TokenInfo) -> Result<(), SyntaxError> {
+        self.reader.seek(info.span.start.idx);
+        let token = if_ok!(scan_regexp(&mut self.reader, &mut self.atoms));
+        info.span = token::Span::new(info.span.start, self.reader.curr_loc());
+        info.tok = token.clone();
+        self.prev = Some(token);
+        Ok(())
+    }
+
+    // Save the state to come back to with `rewind`. The atoms interned
+    // after it are kept, they are still valid.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            reader: self.reader.state(),
+            prev: self.prev.clone(),
+            trailing: self.trailing.clone(),
+            done: self.done,
+            braces: self.braces.clone(),
+        }
+    }
+
+    // Go back to a checkpoint, to lex the same tokens again.
+    pub fn rewind(&mut self, cp: Checkpoint) {
+        self.reader.restore(cp.reader);
+        self.prev = cp.prev;
+        self.trailing = cp.trailing;
+        self.done = cp.done;
+        self.braces = cp.braces;
+    }
+
+    // Return the EOF token, located at the end of the source.
+    pub fn eof_token(&mut self) -> token::TokenInfo {
+        let loc = self.reader.curr_loc();
+        token::TokenInfo::new(token::EOF, token::Span::new(loc, loc),
+                              self.trailing.comments.clone(), self.trailing.newline)
+    }
+
     pub fn is_eof(&self) -> bool {
         self.reader.is_eof()
     }
+
+    // The lines of the source, to map byte offsets to locations.
+    pub fn line_index<'b>(&'b mut self) -> &'b mut LineIndex<'a> {
+        self.reader.line_index()
+    }
+
+    // The atoms of the tokens returned so far.
+    pub fn atoms<'b>(&'b self) -> &'b AtomTable<'a> {
+        &self.atoms
+    }
+    pub fn atoms_mut<'b>(&'b mut self) -> &'b mut AtomTable<'a> {
+        &mut self.atoms
+    }
 }
 
-fn lex_error(reader: &Reader, msg: LexMessage, mtype: LexMessageType) -> ! {
-    if reader.is_eof() {
-        fail!("'SyntasError: {:?} {}", msg, mtype.to_str())
-    } else {
-        fail!("'SyntasError: {:?} {} at row:{}, col:{}",
-            msg, mtype.to_str(), reader.curr_pos().row, reader.curr_pos().col)
-    }   
+// Iterate over the tokens. An error ends the iteration.
+impl<'a> Iterator<Result<token::TokenInfo, SyntaxError>> for Lexer<'a> {
+    fn next(&mut self) -> Option<Result<token::TokenInfo, SyntaxError>> {
+        if self.done {
+            return None;
+        }
+        match self.next_token() {
+            Ok(Some(info)) => Some(Ok(info)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
 }
 
-// Return the next token.
-// Move cursor behind the token.
-fn next_token(reader: &mut Reader) -> token::Token {
-    reader.consume_whitespace_and_comments();
+// Make an error at the current position of the reader.
+fn lex_error(reader: &mut Reader, msg: LexMessage, mtype: LexMessageType) -> SyntaxError {
+    SyntaxError::new(msg, mtype, reader.curr_loc(), None)
+}
+
+// Check if a '/' following the given token starts a regular expression.
+// This picks between the InputElementRegExp and the InputElementDiv goal
+// symbols (ECMA 7). A '/' can only be a division when it follows something
+// that ends an expression.
+fn is_regexp_allowed(prev: &Option<token::Token>) -> bool {
+    match *prev {
+        None => true,
+        Some(token::LITERAL(_)) | Some(token::IDENT(_)) => false,
+        Some(token::TEMPLATE(token::NoSubstitutionTemplate, _, _))
+            | Some(token::TEMPLATE(token::TemplateTail, _, _)) => false,
+        Some(token::RPAREN) | Some(token::RBRACKET) | Some(token::RBRACE) => false,
+        Some(token::INCREMENT) | Some(token::DECREMENT) => false,
+        Some(token::KEYWORD(token::This)) | Some(token::KEYWORD(token::Super))
+            | Some(token::KEYWORD(token::Null)) | Some(token::KEYWORD(token::True))
+            | Some(token::KEYWORD(token::False)) => false,
+        Some(_) => true,
+    }
+}
 
+// Return the next token. White spaces and comments must be eaten already.
+// Move cursor behind the token.
+fn next_token<'a>(reader: &mut Reader<'a>, atoms: &mut AtomTable<'a>,
+                  regexp_allowed: bool) -> Result<token::Token, SyntaxError> {
     let c = reader.curr();
 
-    if util::is_ident_start(c) {
-        scan_ident(reader)
-    } else if util::is_dec_digit(c) {
-        scan_number(reader)
+    if c == '/' && regexp_allowed {
+        scan_regexp(reader, atoms)
+    } else if util::is_ident_start(c) || (c == '\\' && reader.is_next('u')) {
+        scan_ident(reader, atoms)
+    } else if util::is_dec_digit(c) || (c == '.' && util::is_dec_digit(reader.next())) {
+        scan_number(reader, atoms)
     } else if util::is_quote(c) {
-        scan_string(reader)
-    } else if util::is_newline(c) {
-        scan_newline(reader)
+        scan_string(reader, atoms)
+    } else if c == '`' {
+        scan_template(reader, atoms)
     } else {
         scan_operator_or_structure(reader)  
     }
 }
 
-// Scan identifier.
-fn scan_ident(reader: &mut Reader) -> token::Token {
+// Scan identifier or reserved word. (ECMA 7.6)
+// Unicode escape sequences are decoded in the name. A reserved word written
+// with escapes is an error, but for the ones reserved only in strict mode.
+fn scan_ident<'a>(reader: &mut Reader<'a>, atoms: &mut AtomTable<'a>) -> Result<token::Token, SyntaxError> {
     let start_idx = reader.curr_pos_idx();
-    while util::is_ident_continue(reader.curr()) {
-        reader.bump();
+    // The decoded name. It is built only once an escape is found, otherwise
+    // the name is a slice of the source.
+    let mut name = ~"";
+    let mut escaped = false;
+    loop {
+        if !escaped {
+            reader.skip_ascii_ident_continue();
+        }
+        let is_start = reader.curr_pos_idx() == start_idx;
+        if reader.is_curr('\\') {
+            if !escaped {
+                name.push_str(reader.slice_from(start_idx));
+                escaped = true;
+            }
+            reader.bump();
+            if !reader.bump_if('u') {
+                return Err(lex_error(reader, InvalidUnicodeEscape, LMT_Illegal));
+            }
+            let cp = if_ok!(scan_unicode_escape(reader));
+            match char::from_u32(cp) {
+                Some(c) if (is_start && util::is_ident_start(c))
+                        || (!is_start && util::is_ident_continue(c)) => name.push_char(c),
+                _ => return Err(lex_error(reader, InvalidUnicodeEscape, LMT_Illegal))
+            }
+        } else if !reader.is_eof() && util::is_ident_continue(reader.curr()) {
+            let c = reader.bump_curr();
+            if escaped {
+                name.push_char(c);
+            }
+        } else {
+            break;
+        }
     }
-    do reader.with_str_from(start_idx) |ident| {
-        token::IDENT(ident.to_owned())
+    if escaped {
+        // Words reserved only in strict mode code may be escaped, and are the
+        // same token as when spelled out.
+        match from_str::<token::Keyword>(name) {
+            Some(keyword) if keyword.is_strict_reserved() => Ok(token::KEYWORD(keyword)),
+            Some(_) => Err(lex_error(reader, EscapedKeyword, LMT_None)),
+            None => Ok(token::IDENT(atoms.intern(name)))
+        }
+    } else {
+        let name = reader.slice_from(start_idx);
+        match from_str::<token::Keyword>(name) {
+            Some(keyword) => Ok(token::KEYWORD(keyword)),
+            None => Ok(token::IDENT(atoms.intern_borrowed(name)))
+        }
     }
 }
 
-// Scan hexadecimal digits and return the string of it.
-fn scan_digits(reader: &mut Reader, radix: uint) -> ~str {
-    let mut res = ~"";
-    while !reader.is_eof() {
-        let c = reader.curr();
-        match char::to_digit(c, radix) {
-            Some(_) => {
-                res.push_char(c);
-                reader.bump();
-            }
-            None => break
-        }
-    };
-    res
+// Scan digits in the given radix and return the string of them.
+fn scan_digits<'a>(reader: &mut Reader<'a>, radix: uint) -> &'a str {
+    let start_idx = reader.curr_pos_idx();
+    while !reader.is_eof() && char::to_digit(reader.curr(), radix).is_some() {
+        reader.bump();
+    }
+    reader.slice_from(start_idx)
 }
 
-// Scan exponent part of a number from current position.
-fn scan_exponent(reader: &mut Reader) -> Option<~str> {
-    let mut res = ~"";
-    let mut c = reader.curr();
+// Scan exponent part of a number from current position and return its
+// value. Huge exponents saturate, the number is an infinity or a zero anyway.
+fn scan_exponent(reader: &mut Reader) -> Result<int, SyntaxError> {
+    let c = reader.curr();
     if c == 'e' || c == 'E' {
-        res.push_char(c);
         reader.bump();
 
-        c = reader.curr();
-        if c == '+' || c == '-' {
-            res.push_char(c);
+        let negative = reader.is_curr('-');
+        if reader.is_curr('+') || reader.is_curr('-') {
             reader.bump();
         }
         let exponent = scan_digits(reader, 10u);
-        if exponent.len() > 0u {
-            return Some(res + exponent);
-        } else {
-            lex_error(reader, UnexpectedToken, LMT_Illegal);
+        if exponent.len() == 0u {
+            return Err(lex_error(reader, UnexpectedToken, LMT_Illegal));
+        }
+        let mut value = 0;
+        for c in exponent.chars() {
+            if value < 100000 {
+                value = value * 10 + char::to_digit(c, 10u).unwrap() as int;
+            }
         }
+        Ok(if negative { -value } else { value })
     } else {
-        return None;
+        Ok(0)
     }
 }
 
-// Scan number from current position.
-fn scan_number(reader: &mut Reader) -> token::Token {
-    let mut num_str;
-    let mut base = 10u;
+// Scan the decimal part of a number from current position. Return the
+// digits of the integer part, the digits of the fraction part and the
+// decimal exponent.
+fn scan_decimal<'a>(reader: &mut Reader<'a>) -> Result<(&'a str, &'a str, int), SyntaxError> {
+    let digits = scan_digits(reader, 10u);
+    let mut fraction = "";
 
-    // Check if the number is a hex.
-    // TODO: manage oct numbers.
-    if reader.is_curr('0') && reader.is_next('x') {
-        reader.bump();
+    // Scan float part.
+    if reader.bump_if('.') {
+        fraction = scan_digits(reader, 10u);
+    }
+
+    // Scan exponent part if it's exist.
+    let exp = if_ok!(scan_exponent(reader));
+    Ok((digits, fraction, exp))
+}
+
+// The value of the decimal number "<digits>.<fraction>e<exp>".
+fn decimal_value(digits: &str, fraction: &str, exp: int) -> f64 {
+    let exp = exp - fraction.len() as int;
+    if fraction.is_empty() {
+        num::decimal_to_f64(digits, exp)
+    } else {
+        let mut all = digits.to_owned();
+        all.push_str(fraction);
+        num::decimal_to_f64(all, exp)
+    }
+}
+
+// Scan number from current position. (ECMA 7.8.3, B.1.1)
+// Return both the value and the raw source of the number.
+fn scan_number<'a>(reader: &mut Reader<'a>, atoms: &mut AtomTable<'a>) -> Result<token::Token, SyntaxError> {
+    let start_idx = reader.curr_pos_idx();
+    let value;
+
+    if reader.is_curr('0') && (reader.is_next('x') || reader.is_next('X')
+            || reader.is_next('o') || reader.is_next('O')
+            || reader.is_next('b') || reader.is_next('B')) {
+        // Hex, octal or binary integer.
         reader.bump();
-        base = 16u;
+        let radix = match reader.bump_curr() {
+            'x' | 'X' => 16u,
+            'o' | 'O' => 8u,
+            _ => 2u,
+        };
+        let digits = scan_digits(reader, radix);
+        if digits.len() == 0 {
+            return Err(lex_error(reader, UnexpectedNumber, LMT_Illegal));
+        }
+        value = num::radix_to_f64(digits, radix);
+    } else if reader.is_curr('0') && util::is_dec_digit(reader.next()) {
+        // A legacy octal integer like "017", unless some digit is not octal.
+        // Then it is a decimal like "019".
+        let digits = scan_digits(reader, 10u);
+        if digits.chars().all(|c| util::is_oct_digit(c)) {
+            value = num::radix_to_f64(digits, 8u);
+        } else {
+            let (_, fraction, exp) = if_ok!(scan_decimal(reader));
+            value = decimal_value(digits, fraction, exp);
+        }
+    } else {
+        let (digits, fraction, exp) = if_ok!(scan_decimal(reader));
+        value = decimal_value(digits, fraction, exp);
     }
 
-    // Scan number.
-    num_str = scan_digits(reader, base);
+    // The source character immediately following a numeric literal must not
+    // be an identifier start or a digit. (ECMA 7.8.3)
+    if !reader.is_eof() && (util::is_ident_start(reader.curr())
+            || util::is_dec_digit(reader.curr())) {
+        return Err(lex_error(reader, UnexpectedNumber, LMT_Illegal));
+    }
 
-    // Scan float part.
-    //let mut is_float = false;
-    // '.' may be followed by an idntifie. in that case, the number is not a float.
-    if reader.is_curr('.') && !util::is_ident_start(reader.next()) {
-        // Hex number could not have float part.
-        if base == 16u {
-            lex_error(reader, UnexpectedNumber, LMT_Illegal);
-        }
-        //is_float = true;
+    let raw = atoms.intern_borrowed(reader.slice_from(start_idx));
+    Ok(token::LITERAL(token::LIT_NUMERIC(value, raw)))
+}
+
+// Scan exactly `n` hexadecimal digits and return their value.
+fn scan_hex_digits(reader: &mut Reader, n: uint) -> Option<u32> {
+    let mut value = 0u32;
+    for _ in range(0, n) {
+        match char::to_digit(reader.curr(), 16u) {
+            Some(d) if !reader.is_eof() => {
+                value = value * 16 + d as u32;
+                reader.bump();
+            }
+            _ => return None
+        }
+    }
+    Some(value)
+}
+
+// Scan the rest of a unicode escape sequence after "\u" and return the code
+// point. Both the "XXXX" and the "{X...}" forms are accepted. (ECMA 11.8.4)
+fn scan_unicode_escape(reader: &mut Reader) -> Result<u32, SyntaxError> {
+    if reader.bump_if('{') {
+        let mut value = 0u32;
+        let mut digits = 0u;
+        while !reader.is_curr('}') {
+            match char::to_digit(reader.curr(), 16u) {
+                Some(d) if !reader.is_eof() => {
+                    value = value * 16 + d as u32;
+                    if value > 0x10FFFF {
+                        return Err(lex_error(reader, InvalidUnicodeEscape, LMT_Illegal));
+                    }
+                    digits += 1;
+                    reader.bump();
+                }
+                _ => return Err(lex_error(reader, InvalidUnicodeEscape, LMT_Illegal))
+            }
+        }
         reader.bump();
-        num_str.push_char('.');
-        num_str.push_str(scan_digits(reader, 10u));
+        if digits == 0 {
+            return Err(lex_error(reader, InvalidUnicodeEscape, LMT_Illegal));
+        }
+        Ok(value)
+    } else {
+        match scan_hex_digits(reader, 4) {
+            Some(value) => Ok(value),
+            None => Err(lex_error(reader, InvalidUnicodeEscape, LMT_Illegal))
+        }
     }
+}
 
-    // Scan exponent part if it's exist.
-    match scan_exponent(reader) {
-        Some(ref exp) => {
-            //is_float = true;
-            num_str.push_str(*exp);
+// Append a code point to a string.
+// Strings are UTF-16 in ECMAScript, so a surrogate pair written as two
+// escapes stands for one character. A high surrogate waits in `pending` for
+// its pair. Unpaired surrogates can not be held by a `~str` and are replaced
+// with U+FFFD.
+fn push_code_point(string: &mut ~str, pending: &mut Option<u32>, cp: u32) {
+    match *pending {
+        Some(high) if cp >= 0xDC00 && cp <= 0xDFFF => {
+            *pending = None;
+            let cp = 0x10000 + ((high - 0xD800) << 10) + (cp - 0xDC00);
+            string.push_char(char::from_u32(cp).unwrap());
+            return;
+        }
+        Some(_) => {
+            *pending = None;
+            string.push_char('\uFFFD');
         }
         None => ()
     }
-    token::LITERAL(token::LIT_NUMERIC(num_str))
+    if cp >= 0xD800 && cp <= 0xDBFF {
+        *pending = Some(cp);
+    } else {
+        string.push_char(char::from_u32(cp).unwrap_or('\uFFFD'));
+    }
 }
 
-// Scan string literal.
-fn scan_string(reader: &mut Reader) -> token::Token {
+// Scan string literal. (ECMA 7.8.4)
+// Return both the cooked value and the raw source including the quotes.
+fn scan_string<'a>(reader: &mut Reader<'a>, atoms: &mut AtomTable<'a>) -> Result<token::Token, SyntaxError> {
     assert!(reader.is_curr('\'') || reader.is_curr('"'));
 
+    let start_idx = reader.curr_pos_idx();
+    // The cooked value. It is built only once an escape is found, otherwise
+    // the value is a slice of the source.
     let mut string = ~"";
+    let mut escaped = false;
+    let mut pending = None;
     let quote = reader.bump_curr();
     while !reader.bump_if(quote) {
         if reader.is_eof() {
-            lex_error(reader, UnexpectedToken, LMT_Illegal);
+            return Err(lex_error(reader, UnexpectedToken, LMT_Illegal));
         }
-        if reader.bump_if('\\') {
-            match reader.curr() {
-                'n' => { reader.bump(); string.push_char('\n'); }
-                'r' => { reader.bump(); string.push_char('\r'); }
-                't' => { reader.bump(); string.push_char('\t'); }
-                'v' => { reader.bump(); string.push_char('\x0B'); }
-                // TODO: Handle {\u, \x, \b, \f}.
-                _ => { string.push_char(reader.bump_curr()); }
-            }
-        } else if util::is_newline(reader.curr()) {
-            lex_error(reader, UnexpectedToken, LMT_Illegal);
+        if reader.is_curr('\\') {
+            if !escaped {
+                string.push_str(reader.slice_from(start_idx + 1));
+                escaped = true;
+            }
+            reader.bump();
+            let c = reader.bump_curr();
+            match c {
+                'b' => push_code_point(&mut string, &mut pending, 0x08),
+                'f' => push_code_point(&mut string, &mut pending, 0x0C),
+                'n' => push_code_point(&mut string, &mut pending, 0x0A),
+                'r' => push_code_point(&mut string, &mut pending, 0x0D),
+                't' => push_code_point(&mut string, &mut pending, 0x09),
+                'v' => push_code_point(&mut string, &mut pending, 0x0B),
+                'x' => {
+                    match scan_hex_digits(reader, 2) {
+                        Some(cp) => push_code_point(&mut string, &mut pending, cp),
+                        None => return Err(lex_error(reader, InvalidHexEscape, LMT_Illegal))
+                    }
+                }
+                'u' => {
+                    let cp = if_ok!(scan_unicode_escape(reader));
+                    push_code_point(&mut string, &mut pending, cp);
+                }
+                // Line continuation. CRLF counts as a single line terminator.
+                '\r' => { reader.bump_if('\n'); }
+                '\n' | '\u2028' | '\u2029' => (),
+                // "\0" not followed by a digit is the null character.
+                '0' if !util::is_dec_digit(reader.curr()) => {
+                    push_code_point(&mut string, &mut pending, 0);
+                }
+                // Legacy octal escape sequences. (ECMA B.1.2)
+                '0'..'7' => {
+                    let mut cp = char::to_digit(c, 8u).unwrap() as u32;
+                    let max_len = if c <= '3' { 3u } else { 2u };
+                    let mut len = 1u;
+                    while len < max_len && util::is_oct_digit(reader.curr()) {
+                        cp = cp * 8 + char::to_digit(reader.bump_curr(), 8u).unwrap() as u32;
+                        len += 1;
+                    }
+                    push_code_point(&mut string, &mut pending, cp);
+                }
+                // Any other character, including '8' and '9', escapes itself.
+                _ => push_code_point(&mut string, &mut pending, c as u32)
+            }
+        } else if reader.is_curr('\n') || reader.is_curr('\r') {
+            // U+2028 and U+2029 are allowed in string literals. (ES2019)
+            return Err(lex_error(reader, UnexpectedToken, LMT_Illegal));
+        } else if escaped {
+            push_code_point(&mut string, &mut pending, reader.bump_curr() as u32);
         } else {
-            string.push_char(reader.bump_curr());
+            reader.bump();
         }
     }
-    token::LITERAL(token::LIT_STRING(string))
+    let cooked = if escaped {
+        if pending.is_some() {
+            string.push_char('\uFFFD');
+        }
+        atoms.intern(string)
+    } else {
+        // The quotes are one byte each.
+        atoms.intern_borrowed(reader.slice(start_idx + 1, reader.curr_pos_idx() - 1))
+    };
+    let raw = atoms.intern_borrowed(reader.slice_from(start_idx));
+    Ok(token::LITERAL(token::LIT_STRING(cooked, raw)))
 }
 
-// Scan newline.
-fn scan_newline(reader: &mut Reader) -> token::Token {
-    lex_error(reader, NotImplemented, LMT_None)
+// Scan a template from its start '`', or from the '}' ending a substitution.
+// (ECMA 11.8.6) Return the part with its cooked and raw values. Both have
+// CR and CRLF turned into LF. An invalid escape leaves no cooked value, as
+// in a tagged template. Untagged templates must be checked by the parser.
+fn scan_template<'a>(reader: &mut Reader<'a>, atoms: &mut AtomTable<'a>) -> Result<token::Token, SyntaxError> {
+    let head = reader.bump_curr() == '`';
+    let start_idx = reader.curr_pos_idx();
+    // The cooked value. It is built only once an escape or a CR is found,
+    // otherwise the value is a slice of the source.
+    let mut string = ~"";
+    let mut normalized = false;
+    let mut valid = true;
+    let mut has_cr = false;
+    let mut pending = None;
+    let part;
+    let end_idx;
+    loop {
+        if reader.is_eof() {
+            return Err(lex_error(reader, UnterminatedTemplate, LMT_Illegal));
+        }
+        let c = reader.curr();
+        if c == '`' {
+            part = if head { token::NoSubstitutionTemplate } else { token::TemplateTail };
+            end_idx = reader.curr_pos_idx();
+            reader.bump();
+            break;
+        }
+        if c == '$' && reader.is_next('{') {
+            part = if head { token::TemplateHead } else { token::TemplateMiddle };
+            end_idx = reader.curr_pos_idx();
+            reader.bump();
+            reader.bump();
+            break;
+        }
+        if (c == '\\' || c == '\r') && !normalized {
+            string.push_str(reader.slice_from(start_idx));
+            normalized = true;
+        }
+        if c == '\\' {
+            reader.bump();
+            let c = reader.bump_curr();
+            match c {
+                'b' => push_code_point(&mut string, &mut pending, 0x08),
+                'f' => push_code_point(&mut string, &mut pending, 0x0C),
+                'n' => push_code_point(&mut string, &mut pending, 0x0A),
+                'r' => push_code_point(&mut string, &mut pending, 0x0D),
+                't' => push_code_point(&mut string, &mut pending, 0x09),
+                'v' => push_code_point(&mut string, &mut pending, 0x0B),
+                'x' => {
+                    match scan_hex_digits(reader, 2) {
+                        Some(cp) => push_code_point(&mut string, &mut pending, cp),
+                        None => valid = false
+                    }
+                }
+                'u' => {
+                    match scan_unicode_escape(reader) {
+                        Ok(cp) => push_code_point(&mut string, &mut pending, cp),
+                        Err(_) => valid = false
+                    }
+                }
+                // Line continuation.
+                '\r' => {
+                    has_cr = true;
+                    reader.bump_if('\n');
+                }
+                '\n' | '\u2028' | '\u2029' => (),
+                '0' if !util::is_dec_digit(reader.curr()) => {
+                    push_code_point(&mut string, &mut pending, 0);
+                }
+                // No legacy octal escapes in templates.
+                '0'..'9' => valid = false,
+                _ => push_code_point(&mut string, &mut pending, c as u32)
+            }
+        } else if c == '\r' {
+            has_cr = true;
+            reader.bump();
+            reader.bump_if('\n');
+            push_code_point(&mut string, &mut pending, 0x0A);
+        } else if normalized {
+            push_code_point(&mut string, &mut pending, reader.bump_curr() as u32);
+        } else {
+            reader.bump();
+        }
+    }
+
+    let cooked = if !valid {
+        None
+    } else if normalized {
+        if pending.is_some() {
+            string.push_char('\uFFFD');
+        }
+        Some(atoms.intern(string))
+    } else {
+        Some(atoms.intern_borrowed(reader.slice(start_idx, end_idx)))
+    };
+    let raw_src = reader.slice(start_idx, end_idx);
+    let raw = if has_cr {
+        atoms.intern(raw_src.replace("\r\n", "\n").replace("\r", "\n"))
+    } else {
+        atoms.intern_borrowed(raw_src)
+    };
+    Ok(token::TEMPLATE(part, cooked, raw))
 }
 
-// Scan regular expression.
-fn scan_regexp(reader: &mut Reader) -> token::Token {
-    lex_error(reader, NotImplemented, LMT_None)
+// Scan regular expression literal. (ECMA 7.8.5)
+// The body and the flags are kept apart. The body is not validated beyond
+// what is needed to find its end.
+fn scan_regexp<'a>(reader: &mut Reader<'a>, atoms: &mut AtomTable<'a>) -> Result<token::Token, SyntaxError> {
+    assert!(reader.is_curr('/'));
+    reader.bump();
+
+    let body_idx = reader.curr_pos_idx();
+    let mut in_class = false;
+    loop {
+        if reader.is_eof() || util::is_newline(reader.curr()) {
+            return Err(lex_error(reader, UnterminatedRegExp, LMT_Illegal));
+        }
+        match reader.curr() {
+            '\\' => {
+                reader.bump();
+                // A backslash escapes any character but a line terminator.
+                if reader.is_eof() || util::is_newline(reader.curr()) {
+                    return Err(lex_error(reader, UnterminatedRegExp, LMT_Illegal));
+                }
+            }
+            // A '/' in a character class does not end the literal.
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => break,
+            _ => ()
+        }
+        reader.bump();
+    }
+    let body = reader.slice_from(body_idx);
+    reader.bump();
+
+    let flags_idx = reader.curr_pos_idx();
+    while !reader.is_eof() && util::is_ident_continue(reader.curr()) {
+        let c = reader.curr();
+        match c {
+            'g' | 'i' | 'm' | 's' | 'u' | 'y' if !reader.slice_from(flags_idx).contains_char(c) => {
+                reader.bump();
+            }
+            _ => return Err(lex_error(reader, InvalidRegExpFlags, LMT_Illegal))
+        }
+    }
+    let flags = reader.slice_from(flags_idx);
+    Ok(token::LITERAL(token::LIT_REGEXP(atoms.intern_borrowed(body), atoms.intern_borrowed(flags))))
 }
 
 // Scan operators or sturctural symbols.
-fn scan_operator_or_structure(reader: &mut Reader) -> token::Token {
-    // Check if a binary operation is a form of an assignment.
+fn scan_operator_or_structure(reader: &mut Reader) -> Result<token::Token, SyntaxError> {
+    // Check if a binary operation is a form of an assignment. The operator
+    // itself must be eaten already.
     fn binop(reader: &mut Reader, op: token::Binop) -> token::Token {
-        reader.bump();
         if reader.bump_if('=') {
-            return token::BINOPEQ(op);
+            token::BINOPEQ(op)
         } else {
-            return token::BINOP(op);
+            token::BINOP(op)
         }
     }
 
-    match reader.curr() {
+    let token = match reader.curr() {
         '=' => {
             reader.bump();
             if reader.bump_if('=') {
@@ -233,6 +721,8 @@ fn scan_operator_or_structure(reader: &mut Reader) -> token::Token {
                 } else {
                     token::EQ // "=="
                 }
+            } else if reader.bump_if('>') {
+                token::ARROW // "=>"
             } else {
                 token::ASSIGN // "="
             }
@@ -261,7 +751,7 @@ fn scan_operator_or_structure(reader: &mut Reader) -> token::Token {
             reader.bump();
             match reader.curr() {
                 '=' => { reader.bump(); token::GE } // ">="
-                '>' => { 
+                '>' => {
                     reader.bump();
                     if reader.bump_if('>') {
                         binop(reader, token::URSH) // ">>>" or ">>>="
@@ -291,30 +781,69 @@ fn scan_operator_or_structure(reader: &mut Reader) -> token::Token {
         '|' => {
             reader.bump();
             if reader.bump_if('|') {
-                token::OR // "||"
+                if reader.bump_if('=') {
+                    token::OR_EQ // "||="
+                } else {
+                    token::OR // "||"
+                }
             } else {
                 binop(reader, token::BITWISE_OR) // "|" or "|="
             }
         }
         '&' => {
             reader.bump();
-            if reader.is_curr('&') {
-                reader.bump();
-                token::AND //'&&'
+            if reader.bump_if('&') {
+                if reader.bump_if('=') {
+                    token::AND_EQ // "&&="
+                } else {
+                    token::AND // "&&"
+                }
+            } else {
+                binop(reader, token::BITWISE_AND) // "&" or "&="
+            }
+        }
+        '*' => {
+            reader.bump();
+            if reader.bump_if('*') {
+                binop(reader, token::EXP) // "**" or "**="
             } else {
-                binop(reader, token::BITWISE_AND) // '&' or '&&'
+                binop(reader, token::MUL) // "*" or "*="
             }
         }
-        '*' => { reader.bump(); binop(reader, token::MUL) } // "*" or "*="
         '/' => { reader.bump(); binop(reader, token::DIV) } // "/" or "/="
+        '%' => { reader.bump(); binop(reader, token::MOD) } // "%" or "%="
         '^' => { reader.bump(); binop(reader, token::BITWISE_XOR) } // "^" or "^="
+        '?' => {
+            reader.bump();
+            if reader.bump_if('?') {
+                if reader.bump_if('=') {
+                    token::NULLISH_EQ // "??="
+                } else {
+                    token::NULLISH // "??"
+                }
+            } else if reader.is_curr('.') && !util::is_dec_digit(reader.next()) {
+                // "a?.5:b" is a conditional expression.
+                reader.bump();
+                token::QUESTION_DOT // "?."
+            } else {
+                token::HOOK // "?"
+            }
+        }
+        '.' => {
+            reader.bump();
+            if reader.is_curr('.') && reader.is_next('.') {
+                reader.bump();
+                reader.bump();
+                token::ELLIPSIS // "..."
+            } else {
+                token::DOT // "."
+            }
+        }
 
         '~' => { reader.bump(); token::BITWISE_NOT } // "~"
         ';' => { reader.bump(); token::SEMICOLON }
         ',' => { reader.bump(); token::COMMA }
-        '?' => { reader.bump(); token::HOOK }
         ':' => { reader.bump(); token::COLON }
-        '.' => { reader.bump(); token::DOT }
         '[' => { reader.bump(); token::LBRACKET }
         ']' => { reader.bump(); token::RBRACKET }
         '{' => { reader.bump(); token::LBRACE }
@@ -322,48 +851,575 @@ fn scan_operator_or_structure(reader: &mut Reader) -> token::Token {
         '(' => { reader.bump(); token::LPAREN }
         ')' => { reader.bump(); token::RPAREN }
         _=> {
-            lex_error(reader, UnexpectedToken, LMT_Illegal)
+            return Err(lex_error(reader, UnexpectedToken, LMT_Illegal))
         }
-    }
+    };
+    Ok(token)
 }
 
 
 #[cfg(test)]
 mod test {  
-    use super::Lexer;
+    use super::{Lexer, LexerOptions};
+    use super::super::error::{UnexpectedToken, LMT_Illegal};
     use super::super::token;
+    use super::super::reader::Location;
+    use extra::test::BenchHarness;
+
+    fn next(lexer: &mut Lexer) -> Option<token::Token> {
+        lexer.next_token().unwrap().map(|t| t.tok)
+    }
+
+    fn ident(lexer: &mut Lexer, name: &str) -> token::Token {
+        token::IDENT(lexer.atoms_mut().intern(name))
+    }
+
+    fn numeric(lexer: &mut Lexer, value: f64, raw: &str) -> token::Token {
+        token::LITERAL(token::LIT_NUMERIC(value, lexer.atoms_mut().intern(raw)))
+    }
+
+    fn string(lexer: &mut Lexer, cooked: &str, raw: &str) -> token::Token {
+        let atoms = lexer.atoms_mut();
+        token::LITERAL(token::LIT_STRING(atoms.intern(cooked), atoms.intern(raw)))
+    }
+
+    fn template(lexer: &mut Lexer, part: token::TemplatePart, cooked: Option<&str>,
+                raw: &str) -> token::Token {
+        let atoms = lexer.atoms_mut();
+        token::TEMPLATE(part, cooked.map(|s| atoms.intern(s)), atoms.intern(raw))
+    }
+
+    fn regexp(lexer: &mut Lexer, body: &str, flags: &str) -> token::Token {
+        let atoms = lexer.atoms_mut();
+        token::LITERAL(token::LIT_REGEXP(atoms.intern(body), atoms.intern(flags)))
+    }
 
     #[test]
     fn simple_test() {
-        let src = ~"var a = 1; var b=2; a+ b;";
+        let src = "var a = 1; var b=2; a+ b;";
         let mut lexer = Lexer::new(src);
-        assert_eq!(lexer.next_token(), Some(token::IDENT(~"var")));
-        assert_eq!(lexer.next_token(), Some(token::IDENT(~"a")));
-        assert_eq!(lexer.next_token(), Some(token::ASSIGN));
-        assert_eq!(lexer.next_token(), Some(token::LITERAL(token::LIT_NUMERIC(~"1"))));
-        assert_eq!(lexer.next_token(), Some(token::SEMICOLON));
-        assert_eq!(lexer.next_token(), Some(token::IDENT(~"var")));
-        assert_eq!(lexer.next_token(), Some(token::IDENT(~"b")));
-        assert_eq!(lexer.next_token(), Some(token::ASSIGN));
-        assert_eq!(lexer.next_token(), Some(token::LITERAL(token::LIT_NUMERIC(~"2"))));
-        assert_eq!(lexer.next_token(), Some(token::SEMICOLON));
-        assert_eq!(lexer.next_token(), Some(token::IDENT(~"a")));
-        assert_eq!(lexer.next_token(), Some(token::BINOP(token::PLUS)));
-        assert_eq!(lexer.next_token(), Some(token::IDENT(~"b")));
-        assert_eq!(lexer.next_token(), Some(token::SEMICOLON));
-        assert_eq!(lexer.next_token(), None);
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Var)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "a")));
+        assert_eq!(next(&mut lexer), Some(token::ASSIGN));
+        assert_eq!(next(&mut lexer), Some(numeric(&mut lexer, 1.0, "1")));
+        assert_eq!(next(&mut lexer), Some(token::SEMICOLON));
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Var)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "b")));
+        assert_eq!(next(&mut lexer), Some(token::ASSIGN));
+        assert_eq!(next(&mut lexer), Some(numeric(&mut lexer, 2.0, "2")));
+        assert_eq!(next(&mut lexer), Some(token::SEMICOLON));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "a")));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::PLUS)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "b")));
+        assert_eq!(next(&mut lexer), Some(token::SEMICOLON));
+        assert_eq!(next(&mut lexer), None);
     }
 
     #[test]
     fn string_literal() {
-        let src = ~"'simple string token1'";
+        let src = "'simple string token1'";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(next(&mut lexer), Some(string(&mut lexer, "simple string token1", "'simple string token1'")));
+        assert_eq!(next(&mut lexer), None);
+
+        let src = "\"'simple string token2'\"";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(next(&mut lexer), Some(string(&mut lexer, "'simple string token2'", "\"'simple string token2'\"")));
+        assert_eq!(next(&mut lexer), None);
+    }
+
+    #[test]
+    fn string_escape() {
+        fn cooked(src: &str) -> ~str {
+            let mut lexer = Lexer::new(src);
+            match next(&mut lexer) {
+                Some(token::LITERAL(token::LIT_STRING(cooked, _))) => lexer.atoms().get(cooked).to_owned(),
+                t => fail!("{:?} is not a string literal", t)
+            }
+        }
+        assert_eq!(cooked("'\\b\\f\\n\\r\\t\\v'"), ~"\x08\x0C\n\r\t\x0B");
+        assert_eq!(cooked("'\\0'"), ~"\x00");
+        assert_eq!(cooked("'\\x41\\u0042\\u{43}'"), ~"ABC");
+        assert_eq!(cooked("'\\101\\7\\08'"), ~"A\x07\x008");
+        assert_eq!(cooked("'\\8\\q'"), ~"8q");
+        assert_eq!(cooked("'a\\\nb\\\r\nc'"), ~"abc");
+        assert_eq!(cooked("'\\uD83D\\uDE00'"), ~"\U0001F600");
+        assert_eq!(cooked("'\\u{1F600}'"), ~"\U0001F600");
+        assert_eq!(cooked("'\\uD83D'"), ~"\uFFFD");
+
+        let mut lexer = Lexer::new("'\\x41'");
+        assert_eq!(next(&mut lexer), Some(string(&mut lexer, "A", "'\\x41'")));
+    }
+
+    #[test]
+    fn string_invalid_hex_escape() {
+        let mut lexer = Lexer::new("'\\x4'");
+        assert!(lexer.next_token().is_err());
+    }
+
+    #[test]
+    fn keyword() {
+        let src = "if typeof class implements let of";
         let mut lexer = Lexer::new(src);
-        assert_eq!(lexer.next_token(), Some(token::LITERAL(token::LIT_STRING(~"simple string token1"))));
-        assert_eq!(lexer.next_token(), None);
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::If)));
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Typeof)));
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Class)));
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Implements)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "let")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "of")));
+        assert_eq!(next(&mut lexer), None);
 
-        let src = ~"\"'simple string token2'\"";
+        let src = "iff variable";
         let mut lexer = Lexer::new(src);
-        assert_eq!(lexer.next_token(), Some(token::LITERAL(token::LIT_STRING(~"'simple string token2'"))));
-        assert_eq!(lexer.next_token(), None);
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "iff")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "variable")));
+        assert_eq!(next(&mut lexer), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn numeric_literal() {
+        fn number(src: &str) -> (f64, ~str) {
+            let mut lexer = Lexer::new(src);
+            match next(&mut lexer) {
+                Some(token::LITERAL(token::LIT_NUMERIC(value, raw))) => {
+                    (value, lexer.atoms().get(raw).to_owned())
+                }
+                t => fail!("{:?} is not a numeric literal", t)
+            }
+        }
+        assert_eq!(number("42"), (42.0, ~"42"));
+        assert_eq!(number("3.14"), (3.14, ~"3.14"));
+        assert_eq!(number(".5"), (0.5, ~".5"));
+        assert_eq!(number("5."), (5.0, ~"5."));
+        assert_eq!(number("1.5e3"), (1500.0, ~"1.5e3"));
+        assert_eq!(number("2E-2"), (0.02, ~"2E-2"));
+        assert_eq!(number("0xFF"), (255.0, ~"0xFF"));
+        assert_eq!(number("0o17"), (15.0, ~"0o17"));
+        assert_eq!(number("0b101"), (5.0, ~"0b101"));
+        assert_eq!(number("017"), (15.0, ~"017"));
+        assert_eq!(number("019"), (19.0, ~"019"));
+        assert_eq!(number("08.5"), (8.5, ~"08.5"));
+        assert_eq!(number("0.1"), (0.1, ~"0.1"));
+        assert_eq!(number("9007199254740993"), (9007199254740992.0, ~"9007199254740993"));
+
+        let mut lexer = Lexer::new("1..toString");
+        assert_eq!(next(&mut lexer), Some(numeric(&mut lexer, 1.0, "1.")));
+        assert_eq!(next(&mut lexer), Some(token::DOT));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "toString")));
+    }
+
+    #[test]
+    fn numeric_literal_followed_by_ident() {
+        let mut lexer = Lexer::new("3in x");
+        assert!(lexer.next_token().is_err());
+    }
+
+    #[test]
+    fn regexp_literal() {
+        let src = "var re = /ab+c/gi;";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Var)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "re")));
+        assert_eq!(next(&mut lexer), Some(token::ASSIGN));
+        assert_eq!(next(&mut lexer), Some(regexp(&mut lexer, "ab+c", "gi")));
+        assert_eq!(next(&mut lexer), Some(token::SEMICOLON));
+        assert_eq!(next(&mut lexer), None);
+
+        let src = "/[/\\]]\\//";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(next(&mut lexer), Some(regexp(&mut lexer, "[/\\]]\\/", "")));
+        assert_eq!(next(&mut lexer), None);
+    }
+
+    #[test]
+    fn division_or_regexp() {
+        let src = "a / b / c";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "a")));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::DIV)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "b")));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::DIV)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "c")));
+        assert_eq!(next(&mut lexer), None);
+
+        let src = "return /b/";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Return)));
+        assert_eq!(next(&mut lexer), Some(regexp(&mut lexer, "b", "")));
+        assert_eq!(next(&mut lexer), None);
+    }
+
+    #[test]
+    fn rescan_regexp() {
+        // After a block, the '/' is lexed as a division first.
+        let mut lexer = Lexer::new("{}\n/=a/g}");
+        next(&mut lexer);
+        next(&mut lexer);
+        let mut info = lexer.next_token().unwrap().unwrap();
+        assert_eq!(info.tok, token::BINOPEQ(token::DIV));
+        lexer.rescan_regexp(&mut info).unwrap();
+        assert_eq!(info.tok, regexp(&mut lexer, "=a", "g"));
+        assert_eq!(info.span.start, Location::new(3, 1, 0));
+        assert_eq!(info.span.end, Location::new(8, 1, 5));
+        assert!(info.newline_before);
+        assert_eq!(next(&mut lexer), Some(token::RBRACE));
+        assert_eq!(next(&mut lexer), None);
+
+        // What follows is lexed as after a regular expression.
+        let mut lexer = Lexer::new("`${a}` / `${b}`/}");
+        next(&mut lexer);
+        next(&mut lexer);
+        next(&mut lexer);
+        let mut info = lexer.next_token().unwrap().unwrap();
+        lexer.rescan_regexp(&mut info).unwrap();
+        assert_eq!(info.tok, regexp(&mut lexer, " `${b}`", ""));
+        assert_eq!(next(&mut lexer), Some(token::RBRACE));
+        assert_eq!(next(&mut lexer), None);
+    }
+
+    #[test]
+    fn regexp_duplicated_flags() {
+        let mut lexer = Lexer::new("/a/gg");
+        assert!(lexer.next_token().is_err());
+    }
+
+    #[test]
+    fn span() {
+        let src = "var a\n  = 'b';";
+        let mut lexer = Lexer::new(src);
+        let spans = ~[
+            ((0, 0, 0), (3, 0, 3)),
+            ((4, 0, 4), (5, 0, 5)),
+            ((8, 1, 2), (9, 1, 3)),
+            ((10, 1, 4), (13, 1, 7)),
+            ((13, 1, 7), (14, 1, 8)),
+        ];
+        for &((lo, lo_row, lo_col), (hi, hi_row, hi_col)) in spans.iter() {
+            let t = lexer.next_token().unwrap().unwrap();
+            assert_eq!(t.span, token::Span::new(
+                Location::new(lo, lo_row, lo_col), Location::new(hi, hi_row, hi_col)));
+        }
+        assert_eq!(lexer.next_token(), Ok(None));
+        assert_eq!(lexer.eof_token().tok, token::EOF);
+        assert_eq!(lexer.eof_token().span.start, Location::new(14, 1, 8));
+    }
+
+    #[test]
+    fn newline_before() {
+        fn newlines(src: &str) -> ~[bool] {
+            let mut lexer = Lexer::new(src);
+            let mut res = ~[];
+            loop {
+                match lexer.next_token().unwrap() {
+                    Some(t) => res.push(t.newline_before),
+                    None => break
+                }
+            }
+            res
+        }
+        assert_eq!(newlines("a b"), ~[false, false]);
+        assert_eq!(newlines("a\nb"), ~[false, true]);
+        assert_eq!(newlines("\na\r\nb"), ~[true, true]);
+        assert_eq!(newlines("a\u2028b\u2029c"), ~[false, true, true]);
+        assert_eq!(newlines("a // comment\nb"), ~[false, true]);
+        assert_eq!(newlines("a /* comment */ b"), ~[false, false]);
+        assert_eq!(newlines("a /* multi\nline */ b"), ~[false, true]);
+        assert_eq!(newlines("a \n "), ~[false]);
+    }
+
+    #[test]
+    fn syntax_error() {
+        let mut lexer = Lexer::new("a\n  @");
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "a")));
+        let err = lexer.next_token().unwrap_err();
+        assert_eq!(err.msg, UnexpectedToken);
+        assert_eq!(err.mtype, LMT_Illegal);
+        assert_eq!(err.loc, Location::new(4, 1, 2));
+        assert_eq!(err.token, None);
+    }
+
+    #[test]
+    fn unicode_ident() {
+        let mut lexer = Lexer::new("caf\u00e9 \u0061b\\u0063 \\u{1d4b3}x $_\u200c\u200d \u2118");
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "caf\u00e9")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "abc")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "\U0001d4b3x")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "$_\u200c\u200d")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "\u2118")));
+        assert_eq!(next(&mut lexer), None);
+
+        // ZWNJ, digits and combining marks can not start an identifier.
+        assert!(Lexer::new("\u200ca").next_token().is_err());
+        assert!(Lexer::new("\\u0030a").next_token().is_err());
+        assert!(Lexer::new("\u0301a").next_token().is_err());
+        assert!(Lexer::new("a\\u002d").next_token().is_err());
+    }
+
+    #[test]
+    fn escaped_keyword() {
+        assert!(Lexer::new("v\\u0061r").next_token().is_err());
+        assert!(Lexer::new("\\u{69}f").next_token().is_err());
+
+        let mut lexer = Lexer::new("impl\\u0065ments implements");
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Implements)));
+        assert_eq!(next(&mut lexer), Some(token::KEYWORD(token::Implements)));
+    }
+
+    #[test]
+    fn comments() {
+        let src = "/*! license */ a // line\n/*@__PURE__*/ b\n/** end */";
+        let mut lexer = Lexer::new_with_options(src, LexerOptions { comments: true, module: false });
+
+        let a = lexer.next_token().unwrap().unwrap();
+        assert_eq!(a.tok, ident(&mut lexer, "a"));
+        assert_eq!(a.comments, ~[token::Comment::new(token::BlockComment, (2, 12),
+            token::Span::new(Location::new(0, 0, 0), Location::new(14, 0, 14)))]);
+
+        let b = lexer.next_token().unwrap().unwrap();
+        assert_eq!(b.tok, ident(&mut lexer, "b"));
+        assert_eq!(b.comments, ~[
+            token::Comment::new(token::LineComment, (19, 24),
+                token::Span::new(Location::new(17, 0, 17), Location::new(24, 0, 24))),
+            token::Comment::new(token::BlockComment, (27, 36),
+                token::Span::new(Location::new(25, 1, 0), Location::new(38, 1, 13)))]);
+
+        assert_eq!(lexer.next_token(), Ok(None));
+        let eof = lexer.eof_token();
+        assert_eq!(eof.comments.len(), 1);
+        assert_eq!(eof.comments[0].text(src), "* end ");
+        assert!(eof.newline_before);
+
+        // Comments are dropped by default.
+        let mut lexer = Lexer::new(src);
+        assert_eq!(lexer.next_token().unwrap().unwrap().comments, ~[]);
+    }
+
+    #[test]
+    fn iterator() {
+        let mut lexer = Lexer::new("a = 1");
+        let a = ident(&mut lexer, "a");
+        let one = numeric(&mut lexer, 1.0, "1");
+        let toks: ~[token::Token] = lexer.map(|t| t.unwrap().tok).collect();
+        assert_eq!(toks, ~[a, token::ASSIGN, one]);
+
+        // An error ends the iteration.
+        let mut lexer = Lexer::new("a '");
+        assert!(lexer.next().unwrap().is_ok());
+        assert!(lexer.next().unwrap().is_err());
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn interned_atoms() {
+        let mut lexer = Lexer::new("foo 'foo' foo bar");
+        let a = next(&mut lexer).unwrap();
+        let s = next(&mut lexer).unwrap();
+        let b = next(&mut lexer).unwrap();
+        let c = next(&mut lexer).unwrap();
+        assert_eq!(a, b);
+        assert!(a != c);
+        match (a, s) {
+            (token::IDENT(name), token::LITERAL(token::LIT_STRING(cooked, _))) => {
+                assert_eq!(name, cooked);
+                assert_eq!(lexer.atoms().get(name), "foo");
+            }
+            t => fail!("{:?}", t)
+        }
+    }
+
+    #[test]
+    fn borrowed_source() {
+        let src = "var foo = 'bar', 'b\\x61z'";
+        let mut lexer = Lexer::new(src);
+        next(&mut lexer);
+        match (next(&mut lexer), next(&mut lexer), next(&mut lexer)) {
+            (Some(token::IDENT(name)), _, Some(token::LITERAL(token::LIT_STRING(cooked, _)))) => {
+                // Names and values without escapes point into the source.
+                assert_eq!(lexer.atoms().get(name).as_ptr(), src.slice_from(4).as_ptr());
+                assert_eq!(lexer.atoms().get(cooked).as_ptr(), src.slice_from(11).as_ptr());
+            }
+            t => fail!("{:?}", t)
+        }
+        next(&mut lexer);
+        match next(&mut lexer) {
+            Some(token::LITERAL(token::LIT_STRING(cooked, _))) => {
+                assert_eq!(lexer.atoms().get(cooked), "baz");
+            }
+            t => fail!("{:?}", t)
+        }
+    }
+
+    #[bench]
+    fn bench_lexer(b: &mut BenchHarness) {
+        let snippet = "function add(a, b) {\n    // Sum the two.\n    return a + b * 2 / 3;\n}\n\
+                       var s = 'caf\u00e9 \\x41', re = /a[/]b/g, n = 0x1F + 3.5e2;\n";
+        let src = snippet.repeat(1000);
+        b.iter(|| {
+            let mut lexer = Lexer::new(src.as_slice());
+            loop {
+                match lexer.next_token() {
+                    Ok(Some(_)) => (),
+                    _ => break
+                }
+            }
+        });
+        b.bytes = src.len() as u64;
+    }
+
+    // Numbers such as in data tables and generated code.
+    #[bench]
+    fn bench_lexer_numbers(b: &mut BenchHarness) {
+        let snippet = "[0, 1, 42, 3.14, 0.5, 1e3, 2.5e-3, 65535, 0xff, 1.7976931348623157e308],\n";
+        let src = snippet.repeat(1000);
+        b.iter(|| {
+            let mut lexer = Lexer::new(src.as_slice());
+            loop {
+                match lexer.next_token() {
+                    Ok(Some(_)) => (),
+                    _ => break
+                }
+            }
+        });
+        b.bytes = src.len() as u64;
+    }
+
+    // Comments and strings in CJK, where most characters are not ASCII.
+    #[bench]
+    fn bench_lexer_cjk(b: &mut BenchHarness) {
+        let snippet = "// \u5024\u3092\u8fd4\u3059\u3002\nvar \u540d\u524d = '\u3053\u3093\u306b\u3061\u306f\u4e16\u754c', n = \u540d\u524d.length;\n";
+        let src = snippet.repeat(1000);
+        b.iter(|| {
+            let mut lexer = Lexer::new(src.as_slice());
+            loop {
+                match lexer.next_token() {
+                    Ok(Some(_)) => (),
+                    _ => break
+                }
+            }
+        });
+        b.bytes = src.len() as u64;
+    }
+
+    // A minified bundle is a single line, so every column is counted on it.
+    #[bench]
+    fn bench_lexer_single_line(b: &mut BenchHarness) {
+        let snippet = "var s='caf\u00e9',t=s+\"\u3042\";function f(a,b){return a+b*2/3}";
+        let src = snippet.repeat(1000);
+        b.iter(|| {
+            let mut lexer = Lexer::new(src.as_slice());
+            loop {
+                match lexer.next_token() {
+                    Ok(Some(_)) => (),
+                    _ => break
+                }
+            }
+        });
+        b.bytes = src.len() as u64;
+    }
+
+    #[test]
+    fn checkpoint() {
+        let mut lexer = Lexer::new("a\n/b/g");
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "a")));
+        let cp = lexer.checkpoint();
+        let info = lexer.next_token().unwrap().unwrap();
+        assert_eq!(info.tok, token::BINOP(token::DIV));
+        assert_eq!(info.span.start, Location::new(2, 1, 0));
+        lexer.next_token().unwrap();
+        lexer.next_token().unwrap();
+
+        // A '/' after 'a' is still a division after rewinding.
+        lexer.rewind(cp);
+        let again = lexer.next_token().unwrap().unwrap();
+        assert_eq!(again, info);
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "b")));
+
+        // The trivia after the last token is rewound too.
+        let options = LexerOptions { comments: true, module: false };
+        let mut lexer = Lexer::new_with_options("a /* b */", options);
+        let cp = lexer.checkpoint();
+        lexer.next_token().unwrap();
+        assert_eq!(lexer.next_token().unwrap(), None);
+        assert_eq!(lexer.eof_token().comments.len(), 1);
+        lexer.rewind(cp);
+        assert_eq!(lexer.eof_token().comments.len(), 0);
+        assert!(!lexer.eof_token().newline_before);
+    }
+
+    #[test]
+    fn script_only_comments() {
+        let mut lexer = Lexer::new("#!/usr/bin/env node\nx <!-- y\n--> z");
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "x")));
+        assert_eq!(next(&mut lexer), None);
+
+        let options = LexerOptions { comments: false, module: true };
+        assert!(Lexer::new_with_options("#!x", options).next_token().is_err());
+        let mut lexer = Lexer::new_with_options("x <!--y", options);
+        next(&mut lexer);
+        assert_eq!(next(&mut lexer), Some(token::LT));
+    }
+
+    #[test]
+    fn punctuators() {
+        let src = "=> ... ** **= ?. ?? ??= &&= ||= % %= &= & << >>>= a?.5:b";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(next(&mut lexer), Some(token::ARROW));
+        assert_eq!(next(&mut lexer), Some(token::ELLIPSIS));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::EXP)));
+        assert_eq!(next(&mut lexer), Some(token::BINOPEQ(token::EXP)));
+        assert_eq!(next(&mut lexer), Some(token::QUESTION_DOT));
+        assert_eq!(next(&mut lexer), Some(token::NULLISH));
+        assert_eq!(next(&mut lexer), Some(token::NULLISH_EQ));
+        assert_eq!(next(&mut lexer), Some(token::AND_EQ));
+        assert_eq!(next(&mut lexer), Some(token::OR_EQ));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::MOD)));
+        assert_eq!(next(&mut lexer), Some(token::BINOPEQ(token::MOD)));
+        assert_eq!(next(&mut lexer), Some(token::BINOPEQ(token::BITWISE_AND)));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::BITWISE_AND)));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::LSH)));
+        assert_eq!(next(&mut lexer), Some(token::BINOPEQ(token::URSH)));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "a")));
+        assert_eq!(next(&mut lexer), Some(token::HOOK));
+        assert_eq!(next(&mut lexer), Some(numeric(&mut lexer, 0.5, ".5")));
+        assert_eq!(next(&mut lexer), Some(token::COLON));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "b")));
+        assert_eq!(next(&mut lexer), None);
+
+        // Operators take only their own characters.
+        let mut lexer = Lexer::new("a+b");
+        next(&mut lexer);
+        assert_eq!(lexer.next_token().unwrap().unwrap().span.end.idx, 2);
+    }
+
+    #[test]
+    fn template_literal() {
+        let mut lexer = Lexer::new("`a\\n${ {b: `c${d}`} }e\r\nf${g}` / 2");
+        assert_eq!(next(&mut lexer), Some(template(&mut lexer, token::TemplateHead, Some("a\n"), "a\\n")));
+        assert_eq!(next(&mut lexer), Some(token::LBRACE));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "b")));
+        assert_eq!(next(&mut lexer), Some(token::COLON));
+        assert_eq!(next(&mut lexer), Some(template(&mut lexer, token::TemplateHead, Some("c"), "c")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "d")));
+        assert_eq!(next(&mut lexer), Some(template(&mut lexer, token::TemplateTail, Some(""), "")));
+        assert_eq!(next(&mut lexer), Some(token::RBRACE));
+        assert_eq!(next(&mut lexer), Some(template(&mut lexer, token::TemplateMiddle, Some("e\nf"), "e\nf")));
+        assert_eq!(next(&mut lexer), Some(ident(&mut lexer, "g")));
+        assert_eq!(next(&mut lexer), Some(template(&mut lexer, token::TemplateTail, Some(""), "")));
+        assert_eq!(next(&mut lexer), Some(token::BINOP(token::DIV)));
+        assert_eq!(next(&mut lexer), Some(numeric(&mut lexer, 2.0, "2")));
+        assert_eq!(next(&mut lexer), None);
+
+        let mut lexer = Lexer::new("`x`");
+        assert_eq!(next(&mut lexer), Some(template(&mut lexer, token::NoSubstitutionTemplate, Some("x"), "x")));
+    }
+
+    #[test]
+    fn template_invalid_escape() {
+        let mut lexer = Lexer::new("`\\unicode and \\u{110000} \\01`");
+        assert_eq!(next(&mut lexer), Some(template(&mut lexer, token::NoSubstitutionTemplate, None,
+                                                   "\\unicode and \\u{110000} \\01")));
+        assert!(Lexer::new("`a${b").next_token().is_ok());
+        let mut lexer = Lexer::new("`a");
+        assert!(lexer.next_token().is_err());
+    }
+}
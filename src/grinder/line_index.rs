@@ -0,0 +1,244 @@
+use std::uint;
+
+use reader::Location;
+
+// A line with no non-ASCII character found yet.
+static ALL_ASCII: uint = uint::max_value;
+
+// The start offsets of the lines of a source, to map byte offsets to lines
+// and columns. Line terminators are LF, CR, CRLF, U+2028 and U+2029.
+// (ECMA 7.3) A CRLF ends a single line.
+// A leading byte order mark is not a part of the source, so the first line
+// starts after it.
+//
+// Lines are found as far as offsets are asked for, so a reader asking in
+// source order scans the source once. Columns are byte differences up to
+// the first non-ASCII character of a line, and are counted from there on
+// only for such lines.
+pub struct LineIndex<'a> {
+    priv src: &'a str,
+    // The byte offsets where the lines found so far start.
+    priv line_starts: ~[uint],
+    // The byte offset of the first non-ASCII character of each line, or
+    // ALL_ASCII.
+    priv first_wide: ~[uint],
+    // The byte offset up to which lines are found.
+    priv scanned: uint,
+    // The last column counted past a non-ASCII character, to count on from.
+    priv last_col: ColumnCount,
+}
+
+struct ColumnCount {
+    line: uint,
+    idx: uint,
+    // In chars and in UTF-16 code units.
+    chars: uint,
+    units: uint,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> LineIndex<'a> {
+        let start = if src.starts_with("\uFEFF") { "\uFEFF".len() } else { 0 };
+        LineIndex {
+            src: src,
+            line_starts: ~[start],
+            first_wide: ~[ALL_ASCII],
+            scanned: start,
+            last_col: ColumnCount { line: 0, idx: start, chars: 0, units: 0 },
+        }
+    }
+
+    // Find the lines up to the byte offset.
+    fn scan_to(&mut self, idx: uint) {
+        let src = self.src;
+        let bytes = src.as_bytes();
+        let mut i = self.scanned;
+        while i < idx {
+            let b = bytes[i];
+            if b < 0x80 {
+                // The line of a CRLF starts after the LF.
+                if b == 0x0A || (b == 0x0D && !(i + 1 < bytes.len() && bytes[i + 1] == 0x0A)) {
+                    self.line_starts.push(i + 1);
+                    self.first_wide.push(ALL_ASCII);
+                }
+                i += 1;
+                continue;
+            }
+            let last = self.first_wide.len() - 1;
+            if self.first_wide[last] == ALL_ASCII {
+                self.first_wide[last] = i;
+            }
+            // U+2028 and U+2029 are E2 80 A8 and E2 80 A9 in UTF-8.
+            if b == 0xE2 && bytes[i + 1] == 0x80 && (bytes[i + 2] == 0xA8 || bytes[i + 2] == 0xA9) {
+                self.line_starts.push(i + 3);
+                self.first_wide.push(ALL_ASCII);
+            }
+            // The source is valid UTF-8, so `b` is a leading byte.
+            i += if b >= 0xF0 { 4 } else if b >= 0xE0 { 3 } else { 2 };
+        }
+        if i > self.scanned {
+            self.scanned = i;
+        }
+    }
+
+    // The number of lines found so far. It is the number of lines of the
+    // source once the end is asked for.
+    #[inline]
+    pub fn line_count(&self) -> uint {
+        self.line_starts.len()
+    }
+
+    // Return the byte offset where the line starts. Lines count from 0.
+    // The first line starts after the byte order mark, if any.
+    #[inline]
+    pub fn line_start(&self, line: uint) -> uint {
+        self.line_starts[line]
+    }
+
+    // Return the line containing the byte offset. Lines count from 0.
+    pub fn line_of(&mut self, idx: uint) -> uint {
+        assert!(idx <= self.src.len());
+        self.scan_to(idx);
+        // Offsets are mostly asked for on the last line found.
+        let last = self.line_starts.len() - 1;
+        if self.line_starts[last] <= idx {
+            return last;
+        }
+        // Find the last line starting at or before `idx`.
+        let mut lo = 0u;
+        let mut hi = last;
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.line_starts[mid] <= idx {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    // Return the line and the column in chars and in UTF-16 code units of
+    // the byte offset.
+    fn line_col_both(&mut self, idx: uint) -> (uint, uint, uint) {
+        let line = self.line_of(idx);
+        let start = self.line_starts[line];
+        let wide = self.first_wide[line];
+        if idx <= wide {
+            return (line, idx - start, idx - start);
+        }
+        let from = if self.last_col.line == line && wide <= self.last_col.idx
+                && self.last_col.idx <= idx {
+            self.last_col
+        } else {
+            ColumnCount { line: line, idx: wide, chars: wide - start, units: wide - start }
+        };
+        let mut chars = from.chars;
+        let mut units = from.units;
+        let src = self.src;
+        for &b in src.as_bytes().slice(from.idx, idx).iter() {
+            // Count the leading bytes. The ones of four bytes are outside the
+            // BMP, and take two UTF-16 code units.
+            if b & 0xC0 != 0x80 {
+                chars += 1;
+                units += if b >= 0xF0 { 2 } else { 1 };
+            }
+        }
+        self.last_col = ColumnCount { line: line, idx: idx, chars: chars, units: units };
+        (line, chars, units)
+    }
+
+    // Return the line and the column in chars of the byte offset.
+    pub fn line_col(&mut self, idx: uint) -> (uint, uint) {
+        let (line, chars, _) = self.line_col_both(idx);
+        (line, chars)
+    }
+
+    // Return the line and the column in UTF-16 code units of the byte
+    // offset, as browsers and source maps count them.
+    pub fn line_col_utf16(&mut self, idx: uint) -> (uint, uint) {
+        let (line, _, units) = self.line_col_both(idx);
+        (line, units)
+    }
+
+    // Return the location of the byte offset, with a column in UTF-16 code
+    // units as esprima reports it.
+    pub fn location(&mut self, idx: uint) -> Location {
+        let (row, col) = self.line_col_utf16(idx);
+        Location::new(idx, row, col)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LineIndex;
+
+    #[test]
+    fn lines() {
+        let mut index = LineIndex::new("a\nb\r\nc\rd\u2028e\u2029f");
+        assert_eq!(index.line_col(0), (0, 0));
+        assert_eq!(index.line_col(1), (0, 1));
+        assert_eq!(index.line_col(2), (1, 0));
+        // The LF of a CRLF is on the line of the CR.
+        assert_eq!(index.line_col(4), (1, 2));
+        assert_eq!(index.line_col(5), (2, 0));
+        assert_eq!(index.line_col(7), (3, 0));
+        assert_eq!(index.line_col(11), (4, 0));
+        assert_eq!(index.line_col(15), (5, 0));
+        assert_eq!(index.line_col(16), (5, 1));
+        assert_eq!(index.line_count(), 6);
+
+        // Lines are found as far as asked for, and earlier ones looked up.
+        let mut index = LineIndex::new("a\nb\nc");
+        assert_eq!(index.line_col(2), (1, 0));
+        assert_eq!(index.line_count(), 2);
+        assert_eq!(index.line_col(0), (0, 0));
+        assert_eq!(index.line_col(5), (2, 1));
+        assert_eq!(index.line_count(), 3);
+    }
+
+    #[test]
+    fn columns() {
+        // U+1D4B3 is two UTF-16 code units.
+        let src = "x\n\u00e9\U0001d4b3y";
+        let mut index = LineIndex::new(src);
+        let y = src.len() - 1;
+        assert_eq!(index.line_col(y), (1, 2));
+        assert_eq!(index.line_col_utf16(y), (1, 3));
+        assert_eq!(index.location(y).col, 3);
+
+        // Wide characters on earlier lines do not count.
+        let src = "\u00e9\u2028\U0001d4b3\u3042\r\nz";
+        let mut index = LineIndex::new(src);
+        assert_eq!(index.line_col(5), (1, 0));
+        assert_eq!(index.line_col(12), (1, 2));
+        assert_eq!(index.line_col_utf16(12), (1, 3));
+        assert_eq!(index.line_col_utf16(src.len()), (2, 1));
+        assert_eq!(index.line_count(), 3);
+
+        // Columns are counted on from the last one, or again when going back.
+        let src = "\u3042a\u3042b\u3042c";
+        let mut index = LineIndex::new(src);
+        assert_eq!(index.line_col(4), (0, 2));
+        assert_eq!(index.line_col(8), (0, 4));
+        assert_eq!(index.line_col(3), (0, 1));
+        assert_eq!(index.line_col(src.len()), (0, 6));
+        assert_eq!(index.line_col(0), (0, 0));
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let mut index = LineIndex::new("\uFEFFa\nb");
+        assert_eq!(index.line_start(0), 3);
+        assert_eq!(index.line_col(3), (0, 0));
+        assert_eq!(index.line_col(4), (0, 1));
+        assert_eq!(index.line_col(5), (1, 0));
+    }
+
+    #[test]
+    fn empty() {
+        let mut index = LineIndex::new("");
+        assert_eq!(index.line_count(), 1);
+        assert_eq!(index.line_col(0), (0, 0));
+    }
+}
@@ -0,0 +1,314 @@
+use std::cast;
+use std::char;
+
+// Conversion of numeric literals to IEEE 754 doubles. (ECMA 7.8.3)
+// The mathematical value is kept exactly in big integers and rounded only
+// once, to the nearest double with ties to even. Most literals are short
+// enough to take a fast path without big integers.
+
+// Integers up to this are exact in a double.
+static MAX_EXACT_INT: u64 = 1u64 << 53;
+
+// The powers of ten that are exact in a double.
+static POW10: [f64, ..23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11,
+    1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+
+// Arbitrary precision unsigned integer. Little endian base 2^32 digits.
+#[deriving(Clone)]
+struct BigUint {
+    digits: ~[u32],
+}
+
+impl BigUint {
+    fn from_u32(n: u32) -> BigUint {
+        let mut big = BigUint { digits: ~[] };
+        if n > 0 {
+            big.digits.push(n);
+        }
+        big
+    }
+
+    fn is_zero(&self) -> bool {
+        self.digits.len() == 0
+    }
+
+    // The number of significant bits.
+    fn bits(&self) -> uint {
+        match self.digits.last_opt() {
+            Some(&top) => {
+                let mut n = 0u;
+                while (top >> n) > 0 && n < 32 {
+                    n += 1;
+                }
+                (self.digits.len() - 1) * 32 + n
+            }
+            None => 0
+        }
+    }
+
+    // self = self * m + a
+    fn mul_add_small(&mut self, m: u32, a: u32) {
+        let mut carry = a as u64;
+        for d in self.digits.mut_iter() {
+            let v = (*d as u64) * (m as u64) + carry;
+            *d = v as u32;
+            carry = v >> 32;
+        }
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+
+    // self = self * base ^ exp
+    fn mul_pow(&mut self, base: u32, exp: uint) {
+        for _ in range(0, exp) {
+            self.mul_add_small(base, 0);
+        }
+    }
+
+    fn shl(&self, n: uint) -> BigUint {
+        if self.is_zero() {
+            return self.clone();
+        }
+        let words = n / 32;
+        let bits = n % 32;
+        let mut digits = ~[];
+        for _ in range(0, words) {
+            digits.push(0u32);
+        }
+        let mut carry = 0u32;
+        for &d in self.digits.iter() {
+            if bits == 0 {
+                digits.push(d);
+            } else {
+                digits.push((d << bits) | carry);
+                carry = d >> (32 - bits);
+            }
+        }
+        if carry > 0 {
+            digits.push(carry);
+        }
+        BigUint { digits: digits }
+    }
+
+    fn cmp_big(&self, other: &BigUint) -> Ordering {
+        if self.digits.len() != other.digits.len() {
+            return self.digits.len().cmp(&other.digits.len());
+        }
+        let mut i = self.digits.len();
+        while i > 0 {
+            i -= 1;
+            if self.digits[i] != other.digits[i] {
+                return self.digits[i].cmp(&other.digits[i]);
+            }
+        }
+        Equal
+    }
+
+    // self = self - other. `other` must not be greater than self.
+    fn sub_assign(&mut self, other: &BigUint) {
+        let mut borrow = 0i64;
+        for i in range(0, self.digits.len()) {
+            let rhs = if i < other.digits.len() { other.digits[i] as i64 } else { 0 };
+            let mut v = self.digits[i] as i64 - rhs - borrow;
+            if v < 0 {
+                v += 1i64 << 32;
+                borrow = 1;
+            } else {
+                borrow = 0;
+            }
+            self.digits[i] = v as u32;
+        }
+        assert!(borrow == 0);
+        while self.digits.len() > 0 && *self.digits.last() == 0 {
+            self.digits.truncate(self.digits.len() - 1);
+        }
+    }
+}
+
+// Build a double from q * 2^k where q < 2^53.
+// q is below 2^52 only for subnormal numbers, that is, when k is -1074.
+fn make_f64(q: u64, k: int) -> f64 {
+    let bits = if k > 971 {
+        0x7FF0000000000000u64 // Infinity.
+    } else if q < (1u64 << 52) {
+        q
+    } else {
+        (((k + 1075) as u64) << 52) | (q - (1u64 << 52))
+    };
+    unsafe { cast::transmute(bits) }
+}
+
+// Round u / v to the nearest double. (AlgorithmM by Clinger)
+fn ratio_to_f64(u: &BigUint, v: &BigUint) -> f64 {
+    if u.is_zero() {
+        return 0.0;
+    }
+    // Look for k where 2^52 <= u / v / 2^k < 2^53.
+    let mut k = u.bits() as int - v.bits() as int - 53;
+    loop {
+        if k < -1074 {
+            k = -1074;
+        }
+        let (mut num, den) = if k >= 0 {
+            (u.clone(), v.shl(k as uint))
+        } else {
+            (u.shl((-k) as uint), v.clone())
+        };
+        if num.cmp_big(&den.shl(55)) != Less {
+            k += 2;
+            continue;
+        }
+        // q = num / den, num = num % den
+        // Bit i of q is set if num * 2^(54 - i) >= den * 2^54, so num is
+        // doubled in place instead of den being shifted for every bit.
+        let top = den.shl(54);
+        let mut q = 0u64;
+        let mut i = 55u;
+        while i > 0 {
+            i -= 1;
+            if num.cmp_big(&top) != Less {
+                num.sub_assign(&top);
+                q |= 1u64 << i;
+            }
+            if i > 0 {
+                num.mul_add_small(2, 0);
+            }
+        }
+        if q >= (1u64 << 53) {
+            k += 1;
+            continue;
+        }
+        if q < (1u64 << 52) && k > -1074 {
+            k -= 1;
+            continue;
+        }
+        match num.shl(1).cmp_big(&den) {
+            Greater => q += 1,
+            Equal if q & 1 == 1 => q += 1,
+            _ => ()
+        }
+        if q == (1u64 << 53) {
+            q >>= 1;
+            k += 1;
+        }
+        return make_f64(q, k);
+    }
+}
+
+// The value of the digits in the given radix, if it is exact in a double.
+fn exact_int(digits: &str, radix: uint) -> Option<u64> {
+    let mut n = 0u64;
+    for c in digits.chars() {
+        n = n * (radix as u64) + char::to_digit(c, radix).unwrap() as u64;
+        if n > MAX_EXACT_INT {
+            return None;
+        }
+    }
+    Some(n)
+}
+
+// Convert the digits of an integer in the given radix.
+pub fn radix_to_f64(digits: &str, radix: uint) -> f64 {
+    match exact_int(digits, radix) {
+        Some(n) => return n as f64,
+        None => ()
+    }
+    let mut u = BigUint::from_u32(0);
+    for c in digits.chars() {
+        u.mul_add_small(radix as u32, char::to_digit(c, radix).unwrap() as u32);
+    }
+    ratio_to_f64(&u, &BigUint::from_u32(1))
+}
+
+// Convert the decimal number `digits` * 10^`exp`.
+pub fn decimal_to_f64(digits: &str, exp: int) -> f64 {
+    let digits = digits.trim_left_chars(&'0');
+    let len = digits.len() as int;
+    if len == 0 {
+        return 0.0;
+    }
+    // The value is at least 10^(len + exp - 1) and below 10^(len + exp).
+    if len + exp > 310 {
+        return make_f64(0, 972);
+    }
+    if len + exp < -324 {
+        return 0.0;
+    }
+    // Up to 2^53 and 10^22, both the digits and the power of ten are exact,
+    // so one multiplication or division rounds correctly. (Clinger's fast
+    // path)
+    if exp >= -22 && exp <= 22 {
+        match exact_int(digits, 10u) {
+            Some(n) if exp >= 0 => return n as f64 * POW10[exp as uint],
+            Some(n) => return n as f64 / POW10[(-exp) as uint],
+            None => ()
+        }
+    }
+    let mut u = BigUint::from_u32(0);
+    for c in digits.chars() {
+        u.mul_add_small(10, char::to_digit(c, 10u).unwrap() as u32);
+    }
+    let mut v = BigUint::from_u32(1);
+    if exp >= 0 {
+        u.mul_pow(10, exp as uint);
+    } else {
+        v.mul_pow(10, (-exp) as uint);
+    }
+    ratio_to_f64(&u, &v)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{radix_to_f64, decimal_to_f64};
+    use std::f64;
+
+    #[test]
+    fn decimal() {
+        assert_eq!(decimal_to_f64("0", 0), 0.0);
+        assert_eq!(decimal_to_f64("12345", 0), 12345.0);
+        assert_eq!(decimal_to_f64("1", -1), 0.1);
+        assert_eq!(decimal_to_f64("314", -2), 3.14);
+        assert_eq!(decimal_to_f64("000125", -3), 0.125);
+        assert_eq!(decimal_to_f64("1", 308), 1e308);
+        assert_eq!(decimal_to_f64("17976931348623157", 292), 1.7976931348623157e308);
+    }
+
+    #[test]
+    fn decimal_fast_path() {
+        assert_eq!(decimal_to_f64("1", 0), 1.0);
+        assert_eq!(decimal_to_f64("1", -22), 1e-22);
+        assert_eq!(decimal_to_f64("123456789012345", -22), 1.23456789012345e-8);
+        assert_eq!(decimal_to_f64("9007199254740992", 22), 9.007199254740992e37);
+        // Past 10^22, or past 2^53, big integers are used.
+        assert_eq!(decimal_to_f64("1", 23), 1e23);
+        assert_eq!(decimal_to_f64("9007199254740993", -1), 900719925474099.3);
+    }
+
+    #[test]
+    fn decimal_rounding() {
+        // 2^53 + 1 and 2^53 + 3 are halfway between two doubles.
+        assert_eq!(decimal_to_f64("9007199254740993", 0), 9007199254740992.0);
+        assert_eq!(decimal_to_f64("9007199254740995", 0), 9007199254740996.0);
+        assert_eq!(decimal_to_f64("90071992547409930000000001", -10), 9007199254740994.0);
+    }
+
+    #[test]
+    fn decimal_limits() {
+        assert_eq!(decimal_to_f64("2", 308), f64::infinity);
+        assert_eq!(decimal_to_f64("1", 400), f64::infinity);
+        assert_eq!(decimal_to_f64("5", -324), 4.9406564584124654e-324);
+        assert_eq!(decimal_to_f64("2", -324), 0.0);
+        assert_eq!(decimal_to_f64("1", -400), 0.0);
+    }
+
+    #[test]
+    fn radix() {
+        assert_eq!(radix_to_f64("ff", 16), 255.0);
+        assert_eq!(radix_to_f64("17", 8), 15.0);
+        assert_eq!(radix_to_f64("101", 2), 5.0);
+        assert_eq!(radix_to_f64("20000000000001", 16), 9007199254740992.0);
+    }
+}
@@ -1,396 +1,2028 @@
+use std::util::replace;
+
 use ast;
 use ast::Node;
-
+use error::{SyntaxError, LexMessage, LMT_None};
+use error::{UnexpectedToken, UnexpectedEOS, InvalidLHSInAssignment, InvalidLHSInForIn};
+use error::{IllegalReturn, IllegalBreak, IllegalContinue, UnknownLabel, Redeclaration};
+use error::{MultipleDefaultsInSwitch, NotImplemented};
+use lexer;
 use lexer::Lexer;
+use line_index::LineIndex;
+use reader::Location;
 use token;
 use util;
 
-pub struct Parser {
+pub struct ParserOptions {
+    // Whether nodes get the byte range of their source, like esprima's
+    // `range` option.
+    range: bool,
+}
+
+pub struct Parser<'a> {
     // Lexer
-    lexer: Lexer,
+    lexer: Lexer<'a>,
     // The root of the AST.
     program: ~Node<ast::Program>,
+    priv options: ParserOptions,
     // The current token.
-    priv token: token::Token,
-    // Next token.
-    priv token_next: token::Token,
+    priv token: token::TokenInfo,
+    // Next token. It is lexed when it is first looked at, see `peek`.
+    priv token_next: Option<token::TokenInfo>,
+    // The end of the last consumed token. It is the end of a node being built.
+    priv prev_end: Location,
+    // Whether "in" is a relational operator. It is not in the initialiser of
+    // a "for" statement, where it starts a for-in. (ECMA 12.6)
+    priv allow_in: bool,
+    // Whether a function body is being parsed, where "return" is allowed.
+    // (ECMA 12.9)
+    priv in_function: bool,
+    // Whether the body of an iteration statement is being parsed, where
+    // "break" and "continue" are allowed. (ECMA 12.7, 12.8)
+    priv in_iteration: bool,
+    // Whether the cases of a switch statement are being parsed, where
+    // "break" is allowed. (ECMA 12.8)
+    priv in_switch: bool,
+    // The labels of the statements being parsed, which "break" and
+    // "continue" can name. (ECMA 12.12)
+    priv labels: ~[ast::Atom],
 }
 
-impl Parser {
-    pub fn new(src: ~str) -> Parser {
+// A saved state of a parser, to try one parse and fall back to another.
+#[deriving(Clone)]
+pub struct Checkpoint {
+    priv lexer: lexer::Checkpoint,
+    priv token: token::TokenInfo,
+    priv token_next: Option<token::TokenInfo>,
+    priv prev_end: Location,
+}
+
+impl ParserOptions {
+    pub fn default() -> ParserOptions {
+        ParserOptions {
+            range: false,
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(src: &'a str) -> Result<Parser<'a>, SyntaxError> {
+        Parser::new_with_options(src, ParserOptions::default())
+    }
+
+    pub fn new_with_options(src: &'a str, options: ParserOptions) -> Result<Parser<'a>, SyntaxError> {
         let mut lexer = Lexer::new(src);
-        let token = lexer.next_token().map_default(token::EOF, |token| token);
-        let token_next = lexer.next_token().map_default(token::EOF, |token| token);
+        let token = if_ok!(Parser::next_token_info(&mut lexer));
+        let start = token.span.start;
 
-        Parser {
+        Ok(Parser {
             lexer: lexer,
             program: ~Node::new(
                 ast::SourceLocation::new(
-                    ast::Position::new(0, 0),
-                    ast::Position::new(0, 0)),
+                    Parser::ast_position(start),
+                    Parser::ast_position(start)),
+                None,
                 ast::Program::new()),
+            options: options,
             token: token,
-            token_next: token_next,
-        }
+            token_next: None,
+            prev_end: start,
+            allow_in: true,
+            in_function: false,
+            in_iteration: false,
+            in_switch: false,
+            labels: ~[],
+        })
     }
 
-    pub fn parse(&mut self) {
+    pub fn parse(&mut self) -> Result<(), SyntaxError> {
+        let start = self.token.span.start;
         while !self.is_eof() {
-            self.parse_statement()
+            let stmt = if_ok!(self.parse_statement());
+            self.program.body.push(stmt);
+        }
+        self.program.loc = self.source_location(start);
+        self.program.range = self.source_range(start);
+        Ok(())
+    }
+
+    // The lines of the source, to map byte offsets to locations.
+    pub fn line_index<'b>(&'b mut self) -> &'b mut LineIndex<'a> {
+        self.lexer.line_index()
+    }
+
+    // Save the state to come back to with `rewind`.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            lexer: self.lexer.checkpoint(),
+            token: self.token.clone(),
+            token_next: self.token_next.clone(),
+            prev_end: self.prev_end,
+        }
+    }
+
+    // Go back to a checkpoint. The nodes built since then are not removed.
+    pub fn rewind(&mut self, cp: Checkpoint) {
+        self.lexer.rewind(cp.lexer);
+        self.token = cp.token;
+        self.token_next = cp.token_next;
+        self.prev_end = cp.prev_end;
+    }
+
+    #[inline]
+    fn next_token_info(lexer: &mut Lexer<'a>) -> Result<token::TokenInfo, SyntaxError> {
+        match if_ok!(lexer.next_token()) {
+            Some(token) => Ok(token),
+            None => Ok(lexer.eof_token())
         }
     }
 
     #[inline]
     fn is_eof(&self) -> bool {
-        self.token == token::EOF
+        self.token.tok == token::EOF
     }
 
     #[inline]
-    fn bump(&mut self) {
+    fn bump(&mut self) -> Result<(), SyntaxError> {
         if !self.is_eof() {
-            self.token = self.token_next.clone();
-            self.token_next = self.lexer.next_token().map_default(token::EOF, |token| token);
+            let token = match self.token_next.take() {
+                Some(token) => token,
+                None => if_ok!(Parser::next_token_info(&mut self.lexer))
+            };
+            self.prev_end = self.token.span.end;
+            self.token = token;
         }
+        Ok(())
+    }
+
+    // The token after the current one. It is not lexed until it is needed,
+    // so that the current one can still be lexed again.
+    fn peek<'b>(&'b mut self) -> Result<&'b token::Token, SyntaxError> {
+        if self.token_next.is_none() {
+            self.token_next = Some(if_ok!(Parser::next_token_info(&mut self.lexer)));
+        }
+        Ok(&self.token_next.get_ref().tok)
     }
 
     #[inline]
-    fn bump_curr(&mut self) -> token::Token {
-        let token = self.token.clone();
-        self.bump();
-        token
+    fn bump_curr(&mut self) -> Result<token::Token, SyntaxError> {
+        let token = self.token.tok.clone();
+        if_ok!(self.bump());
+        Ok(token)
     }
 
     #[inline]
-    fn bump_if(&mut self, token: token::Token) -> bool {
+    fn bump_if(&mut self, token: token::Token) -> Result<bool, SyntaxError> {
         if self.is_curr(token) {
-            self.bump();
-            true
+            if_ok!(self.bump());
+            Ok(true)
         } else {
-            false
+            Ok(false)
         }
     }
 
     #[inline]
-    fn bump_if_any(&mut self, tokens: &[token::Token]) -> bool {
+    fn bump_if_any(&mut self, tokens: &[token::Token]) -> Result<bool, SyntaxError> {
         if self.is_curr_any(tokens) {
-            self.bump();
-            true
+            if_ok!(self.bump());
+            Ok(true)
         } else {
-            false
+            Ok(false)
+        }
+    }
+
+    // Consume the current token, which must be `token`.
+    #[inline]
+    fn expect(&mut self, token: token::Token) -> Result<(), SyntaxError> {
+        if if_ok!(self.bump_if(token)) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    // Call `f` with `allow_in` set, and restore it.
+    #[inline]
+    fn with_allow_in<T>(&mut self, allow_in: bool,
+                        f: |&mut Parser<'a>| -> Result<T, SyntaxError>) -> Result<T, SyntaxError> {
+        let saved = self.allow_in;
+        self.allow_in = allow_in;
+        let result = f(self);
+        self.allow_in = saved;
+        result
+    }
+
+    // Call `f` with `in_iteration` set, and restore it.
+    #[inline]
+    fn with_in_iteration<T>(&mut self, in_iteration: bool,
+                            f: |&mut Parser<'a>| -> Result<T, SyntaxError>) -> Result<T, SyntaxError> {
+        let saved = self.in_iteration;
+        self.in_iteration = in_iteration;
+        let result = f(self);
+        self.in_iteration = saved;
+        result
+    }
+
+    // Make an error with the current token.
+    fn error(&self, msg: LexMessage) -> SyntaxError {
+        SyntaxError::new(msg, LMT_None, self.token.span.start, Some(self.token.tok.clone()))
+    }
+
+    // Make an error at a node starting at `start`.
+    fn error_at(&self, msg: LexMessage, start: Location) -> SyntaxError {
+        SyntaxError::new(msg, LMT_None, start, None)
+    }
+
+    // Make an error for the current token, which is not expected here.
+    fn unexpected(&self) -> SyntaxError {
+        if self.is_eof() {
+            self.error(UnexpectedEOS)
+        } else {
+            self.error(UnexpectedToken)
         }
     }
 
     #[inline]
     fn is_curr(&self, token: token::Token) -> bool {
-        self.token == token
+        self.token.tok == token
     }
 
     #[inline]
     fn is_curr_any(&self, tokens: &[token::Token]) -> bool {
-        tokens.iter().any(|token| self.token == *token)
+        tokens.iter().any(|token| self.token.tok == *token)
     }
 
+    // Check if a line terminator comes before the current token.
     #[inline]
-    fn new_node<T>(&self, t: T) -> Node<T> {
-        Node::new(
-            ast::SourceLocation::new(
-                ast::Position::new(0, 0),
-                ast::Position::new(0, 0)),
-            t)
+    fn has_newline_before(&self) -> bool {
+        self.token.newline_before
     }
 
+    // Check if the current token is an Identifier.
+    #[inline]
+    fn is_curr_identifier(&self) -> bool {
+        match self.token.tok {
+            token::IDENT(_) => true,
+            token::KEYWORD(ref keyword) => keyword.is_strict_reserved(),
+            _ => false
+        }
+    }
+
+    // Check if a semicolon can be inserted before the current token by
+    // automatic semicolon insertion. (ECMA 7.9.1)
+    #[inline]
+    fn can_insert_semicolon(&self) -> bool {
+        self.is_eof() || self.is_curr(token::RBRACE) || self.has_newline_before()
+    }
+
+    // Convert a location in the source to a position in the AST.
+    // Lines count from 1 and columns from 0 in the AST, like esprima does.
+    #[inline]
+    fn ast_position(loc: Location) -> ast::Position {
+        ast::Position::new(loc.row as u32 + 1, loc.col as u32)
+    }
+
+    // The location of a node from `start` up to the last consumed token.
+    #[inline]
+    fn source_location(&self, start: Location) -> ast::SourceLocation {
+        ast::SourceLocation::new(Parser::ast_position(start), Parser::ast_position(self.prev_end))
+    }
+
+    #[inline]
+    fn source_range(&self, start: Location) -> Option<(uint, uint)> {
+        if self.options.range {
+            Some((start.idx, self.prev_end.idx))
+        } else {
+            None
+        }
+    }
+
+    // Build a node which starts at `start` and ends with the last consumed
+    // token.
+    #[inline]
+    fn new_node<T>(&self, start: Location, t: T) -> Node<T> {
+        Node::new(self.source_location(start), self.source_range(start), t)
+    }
+
+
+    // ECMA 7.6 Identifier Names and Identifiers
+    // Identifier is an IdentifierName but not a ReservedWord.
+    fn parse_identifier(&mut self) -> Result<Node<ast::Identifier>, SyntaxError> {
+        let start = self.token.span.start;
+        // FIXME: remove copy.
+        let token = self.token.tok.clone();
+        let name = match token {
+            token::IDENT(name) => name,
+            // Not reserved outside strict mode code.
+            token::KEYWORD(keyword) if keyword.is_strict_reserved() => self.keyword_atom(keyword),
+            _ => return Err(self.unexpected())
+        };
+        if_ok!(self.bump());
+        Ok(self.new_node(start, ast::Identifier::new(name)))
+    }
+
+    // Reserved words are names where an IdentifierName is expected, such as
+    // property names.
+    fn parse_identifier_name(&mut self) -> Result<Node<ast::Identifier>, SyntaxError> {
+        let start = self.token.span.start;
+        let token = self.token.tok.clone();
+        let name = match token {
+            token::IDENT(name) => name,
+            token::KEYWORD(keyword) => self.keyword_atom(keyword),
+            _ => return Err(self.unexpected())
+        };
+        if_ok!(self.bump());
+        Ok(self.new_node(start, ast::Identifier::new(name)))
+    }
+
+    fn keyword_atom(&mut self, keyword: token::Keyword) -> ast::Atom {
+        let name = keyword.to_str();
+        self.lexer.atoms_mut().intern(name.as_slice())
+    }
+
+    // ECMA 7.8 Literals
+    fn parse_literal(&mut self) -> Result<Node<ast::Literal>, SyntaxError> {
+        let start = self.token.span.start;
+        let value = match self.token.tok {
+            token::LITERAL(token::LIT_NUMERIC(value, _)) => ast::LV_Number(value),
+            token::LITERAL(token::LIT_STRING(cooked, _)) => ast::LV_String(cooked),
+            token::LITERAL(token::LIT_REGEXP(body, flags)) => ast::LV_RegExp(body, flags),
+            token::KEYWORD(token::True) => ast::LV_Boolean(true),
+            token::KEYWORD(token::False) => ast::LV_Boolean(false),
+            token::KEYWORD(token::Null) => ast::LV_Null,
+            _ => return Err(self.unexpected())
+        };
+        if_ok!(self.bump());
+        Ok(self.new_node(start, ast::Literal::new(value)))
+    }
+
+    // ECMA 11.1 Primary Expressions
+    fn parse_primary_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        // FIXME: remove copy.
+        let token = self.token.tok.clone();
+        match token {
+            token::KEYWORD(token::This) => {
+                if_ok!(self.bump());
+                Ok(ast::ExprThis(~self.new_node(start, ast::ThisExpression)))
+            }
+            token::IDENT(_) => Ok(ast::ExprIdentifier(~if_ok!(self.parse_identifier()))),
+            token::KEYWORD(keyword) if keyword.is_strict_reserved() => {
+                Ok(ast::ExprIdentifier(~if_ok!(self.parse_identifier())))
+            }
+            token::LITERAL(_)
+            | token::KEYWORD(token::True)
+            | token::KEYWORD(token::False)
+            | token::KEYWORD(token::Null) => Ok(ast::ExprLiteral(~if_ok!(self.parse_literal()))),
+            // "in" is an operator again in nested expressions.
+            token::LBRACKET => self.with_allow_in(true, |p| p.parse_array_literal()),
+            token::LBRACE => self.with_allow_in(true, |p| p.parse_object_literal()),
+            token::LPAREN => {
+                if_ok!(self.bump());
+                let exp = if_ok!(self.with_allow_in(true, |p| p.parse_expression()));
+                if_ok!(self.expect(token::RPAREN));
+                Ok(exp)
+            }
+            token::BINOP(token::DIV) | token::BINOPEQ(token::DIV) => {
+                if_ok!(self.rescan_regexp());
+                Ok(ast::ExprLiteral(~if_ok!(self.parse_literal())))
+            }
+            _ => Err(self.unexpected())
+        }
+    }
+
+    // The lexer takes a '/' after a ')' or a '}' for a division, but it
+    // starts a regular expression where a primary expression is expected,
+    // as in "if (a) /b/.test(c)". Lex the current token again as one. Nothing
+    // looks past a '/' before this, so the token after it is not lexed yet.
+    fn rescan_regexp(&mut self) -> Result<(), SyntaxError> {
+        self.lexer.rescan_regexp(&mut self.token)
+    }
+
+    // ECMA 11.1.4 Array Initialiser
+    fn parse_array_literal(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::LBRACKET));
+        let mut elements = ~[];
+        while !if_ok!(self.bump_if(token::RBRACKET)) {
+            if if_ok!(self.bump_if(token::COMMA)) {
+                // An elision is a hole.
+                elements.push(None);
+            } else {
+                elements.push(Some(if_ok!(self.parse_assignment_expression())));
+                // A trailing comma does not add a hole.
+                if !self.is_curr(token::RBRACKET) {
+                    if_ok!(self.expect(token::COMMA));
+                }
+            }
+        }
+        Ok(ast::ExprArray(~self.new_node(start, ast::ArrayExpression::new(elements))))
+    }
+
+    // ECMA 11.1.5 Object Initialiser
+    fn parse_object_literal(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::LBRACE));
+        let mut properties = ~[];
+        while !if_ok!(self.bump_if(token::RBRACE)) {
+            properties.push(if_ok!(self.parse_property_assignment()));
+            if !self.is_curr(token::RBRACE) {
+                if_ok!(self.expect(token::COMMA));
+            }
+        }
+        Ok(ast::ExprObject(~self.new_node(start, ast::ObjectExpression::new(properties))))
+    }
+
+    fn parse_property_assignment(&mut self) -> Result<ast::ObjectExpressionProperty, SyntaxError> {
+        let accessor = {
+            let atoms = self.lexer.atoms();
+            if self.token.tok.is_ident_named(atoms, "get") {
+                Some(ast::Get)
+            } else if self.token.tok.is_ident_named(atoms, "set") {
+                Some(ast::Set)
+            } else {
+                None
+            }
+        };
+        // "get" and "set" followed by a name start an accessor, whose value is
+        // a function.
+        if accessor.is_some() && Parser::is_property_name(if_ok!(self.peek())) {
+            let kind = accessor.unwrap();
+            if_ok!(self.bump());
+            let key = if_ok!(self.parse_property_name());
+            let value = if_ok!(self.parse_accessor_function(kind));
+            return Ok(ast::ObjectExpressionProperty::new(key, value, kind));
+        }
+        let key = if_ok!(self.parse_property_name());
+        if_ok!(self.expect(token::COLON));
+        let value = if_ok!(self.parse_assignment_expression());
+        Ok(ast::ObjectExpressionProperty::new(key, value, ast::Init))
+    }
+
+    // The function of an accessor after its name. A getter has no parameter,
+    // and a setter has one.
+    fn parse_accessor_function(&mut self, kind: ast::ObjectExpressionPropertyKind) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::LPAREN));
+        let mut params = ~[];
+        match kind {
+            ast::Set => params.push(ast::PtrnIdentifier(~if_ok!(self.parse_identifier()))),
+            _ => ()
+        }
+        if_ok!(self.expect(token::RPAREN));
+        let body = if_ok!(self.parse_function_body());
+        let function = self.new_node(start, ast::FunctionBody::new(None, params, ~[], None, Left(body), false, false));
+        Ok(ast::ExprFunction(~self.new_node(start, ast::FunctionExpression::new(function))))
+    }
+
+    // PropertyName : IdentifierName | StringLiteral | NumericLiteral
+    fn parse_property_name(&mut self) -> Result<Either<Node<ast::Literal>, Node<ast::Identifier>>, SyntaxError> {
+        match self.token.tok {
+            token::LITERAL(token::LIT_STRING(_, _))
+            | token::LITERAL(token::LIT_NUMERIC(_, _)) => Ok(Left(if_ok!(self.parse_literal()))),
+            _ => Ok(Right(if_ok!(self.parse_identifier_name())))
+        }
+    }
+
+    fn is_property_name(token: &token::Token) -> bool {
+        match *token {
+            token::IDENT(_)
+            | token::KEYWORD(_)
+            | token::LITERAL(token::LIT_STRING(_, _))
+            | token::LITERAL(token::LIT_NUMERIC(_, _)) => true,
+            _ => false
+        }
+    }
 
     // ECMA 11.2 Left-Hand-Side Expressions
-    fn parse_left_hand_side_expression(&mut self) -> ast::Expression {
-        fail!("Not Implemented");
+    // A call ends the callee of a "new", so "new a.b()()" calls "new a.b()".
+    fn parse_left_hand_side_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if self.is_curr(token::KEYWORD(token::New)) {
+            if_ok!(self.parse_new_expression())
+        } else {
+            if_ok!(self.parse_primary_expression())
+        };
+        loop {
+            exp = if self.is_curr(token::LPAREN) {
+                let args = if_ok!(self.parse_arguments());
+                ast::ExprCall(~self.new_node(start, ast::CallExpression::new(exp, args)))
+            } else if self.is_curr_any([token::DOT, token::LBRACKET]) {
+                if_ok!(self.parse_property_accessor(start, exp))
+            } else {
+                return Ok(exp);
+            }
+        }
+    }
+
+    // MemberExpression, which has no calls but the arguments of "new".
+    fn parse_member_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if self.is_curr(token::KEYWORD(token::New)) {
+            if_ok!(self.parse_new_expression())
+        } else {
+            if_ok!(self.parse_primary_expression())
+        };
+        while self.is_curr_any([token::DOT, token::LBRACKET]) {
+            exp = if_ok!(self.parse_property_accessor(start, exp));
+        }
+        Ok(exp)
+    }
+
+    // new MemberExpression Arguments, or new NewExpression without arguments.
+    fn parse_new_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::New)));
+        let callee = if_ok!(self.parse_member_expression());
+        let args = if self.is_curr(token::LPAREN) {
+            if_ok!(self.parse_arguments())
+        } else {
+            ~[]
+        };
+        Ok(ast::ExprNew(~self.new_node(start, ast::NewExpression::new(callee, args))))
+    }
+
+    // ECMA 11.2.1 Property Accessors
+    // "." IdentifierName or "[" Expression "]" after `object`.
+    fn parse_property_accessor(&mut self, start: Location, object: ast::Expression) -> Result<ast::Expression, SyntaxError> {
+        let member = if if_ok!(self.bump_if(token::DOT)) {
+            let name = if_ok!(self.parse_identifier_name());
+            ast::MemberExpression::new(object, Left(name), false)
+        } else {
+            if_ok!(self.expect(token::LBRACKET));
+            let property = if_ok!(self.with_allow_in(true, |p| p.parse_expression()));
+            if_ok!(self.expect(token::RBRACKET));
+            ast::MemberExpression::new(object, Right(property), true)
+        };
+        Ok(ast::ExprMember(~self.new_node(start, member)))
+    }
+
+    // ECMA 11.2.4 Argument Lists
+    fn parse_arguments(&mut self) -> Result<~[ast::Expression], SyntaxError> {
+        if_ok!(self.expect(token::LPAREN));
+        let mut args = ~[];
+        if !if_ok!(self.bump_if(token::RPAREN)) {
+            loop {
+                args.push(if_ok!(self.with_allow_in(true, |p| p.parse_assignment_expression())));
+                if if_ok!(self.bump_if(token::RPAREN)) {
+                    break;
+                }
+                if_ok!(self.expect(token::COMMA));
+            }
+        }
+        Ok(args)
     }
 
     // ECMA 11.3 Postfix Expressions
-    fn parse_postfix_expression(&mut self) -> ast::Expression {
-        let exp = self.parse_left_hand_side_expression();
-        if self.is_curr_any([token::INCREMENT, token::DECREMENT]) {
-            let op = self.bump_curr();
-            ast::ExprUpdate(~self.new_node(ast::UpdateExpression::new(util::token_to_update_operator(op), exp, false)))
+    fn parse_postfix_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let exp = if_ok!(self.parse_left_hand_side_expression());
+        // No line terminator is allowed before a postfix operator. (ECMA 7.9.1)
+        if self.is_curr_any([token::INCREMENT, token::DECREMENT]) && !self.has_newline_before() {
+            let op = if_ok!(self.bump_curr());
+            Ok(ast::ExprUpdate(~self.new_node(start, ast::UpdateExpression::new(util::token_to_update_operator(op), exp, false))))
         } else {
-            exp
+            Ok(exp)
         }
     }
 
     // ECMA 11.4 Unary Operator
-    fn parse_unary_expression(&mut self) -> ast::Expression {
+    fn parse_unary_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
         if self.is_curr_any([
                 token::BINOP(token::PLUS),
                 token::BINOP(token::MINUS),
                 token::BITWISE_NOT,
                 token::NOT,
-                token::IDENT(~"delete"),
-                token::IDENT(~"void"),
-                token::IDENT(~"typeof")]) {
-            let op = self.bump_curr();
-            let exp = self.parse_unary_expression();
-            ast::ExprUnary(~self.new_node(ast::UnaryExpression::new(util::token_to_unary_operator(op), exp, true)))
+                token::KEYWORD(token::Delete),
+                token::KEYWORD(token::Void),
+                token::KEYWORD(token::Typeof)]) {
+            let op = if_ok!(self.bump_curr());
+            let exp = if_ok!(self.parse_unary_expression());
+            Ok(ast::ExprUnary(~self.new_node(start, ast::UnaryExpression::new(util::token_to_unary_operator(op), exp, true))))
         } else if self.is_curr_any([token::INCREMENT, token::DECREMENT]) {
-            let op = self.bump_curr();
-            let exp = self.parse_unary_expression();
-            ast::ExprUpdate(~self.new_node(ast::UpdateExpression::new(util::token_to_update_operator(op), exp, true)))
+            let op = if_ok!(self.bump_curr());
+            let exp = if_ok!(self.parse_unary_expression());
+            Ok(ast::ExprUpdate(~self.new_node(start, ast::UpdateExpression::new(util::token_to_update_operator(op), exp, true))))
         } else {
             self.parse_postfix_expression()
         }
     }
 
     // ECMA 11.5 Multiplicative Operators
-    fn parse_multiplicative_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_unary_expression();
+    fn parse_multiplicative_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_unary_expression());
         while self.is_curr_any([token::BINOP(token::MUL), token::BINOP(token::DIV), token::BINOP(token::MOD)]) {
-            let op = self.bump_curr();
-            let exp2 = self.parse_unary_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
+            let op = if_ok!(self.bump_curr());
+            let exp2 = if_ok!(self.parse_unary_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
 
     // ECMA 11.6 Additive Operators
-    fn parse_additive_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_multiplicative_expression();
+    fn parse_additive_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_multiplicative_expression());
         while self.is_curr_any([token::BINOP(token::PLUS), token::BINOP(token::MINUS)]) {
-            let op = self.bump_curr();
-            let exp2 = self.parse_multiplicative_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
+            let op = if_ok!(self.bump_curr());
+            let exp2 = if_ok!(self.parse_multiplicative_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
     // ECMA 11.7 Bitwise Shift Operators
-    fn parse_shift_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_additive_expression();
+    fn parse_shift_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_additive_expression());
         while self.is_curr_any([token::BINOP(token::LSH), token::BINOP(token::RSH), token::BINOP(token::URSH)]) {
-            let op = self.bump_curr();
-            let exp2 = self.parse_additive_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
+            let op = if_ok!(self.bump_curr());
+            let exp2 = if_ok!(self.parse_additive_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
 
     // ECMA 11.8 Relational Operators
-    fn parse_relational_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_shift_expression();
-        while self.is_curr_any([token::LT, token::GT, token::LE, token::GE, token::IDENT(~"instanceof"), token::IDENT(~"in")]) {
-            let op = self.bump_curr();
-            let exp2 = self.parse_shift_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
+    fn is_relational_operator(&self) -> bool {
+        match self.token.tok {
+            token::LT | token::GT | token::LE | token::GE | token::KEYWORD(token::Instanceof) => true,
+            token::KEYWORD(token::In) => self.allow_in,
+            _ => false
+        }
+    }
+
+    fn parse_relational_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_shift_expression());
+        while self.is_relational_operator() {
+            let op = if_ok!(self.bump_curr());
+            let exp2 = if_ok!(self.parse_shift_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
 
     // ECMA 11.9 Equality Operators
-    fn parse_equality_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_relational_expression();
+    fn parse_equality_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_relational_expression());
         while self.is_curr_any([token::EQ, token::STRICT_EQ, token::NE, token::STRICT_NE]) {
-            let op = self.bump_curr();
-            let exp2 = self.parse_relational_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
+            let op = if_ok!(self.bump_curr());
+            let exp2 = if_ok!(self.parse_relational_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(util::token_to_binary_operator(op), exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
 
     // ECMA 11.10 Binary Bitwise Operators
-    fn parse_bitwise_and_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_equality_expression();
-        while self.bump_if(token::BINOP(token::BITWISE_AND)) {
-            let exp2 = self.parse_equality_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(ast::BO_BITWISE_AND, exp, exp2)));
+    fn parse_bitwise_and_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_equality_expression());
+        while if_ok!(self.bump_if(token::BINOP(token::BITWISE_AND))) {
+            let exp2 = if_ok!(self.parse_equality_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(ast::BO_BITWISE_AND, exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
-    fn parse_bitwise_xor_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_bitwise_and_expression();
-        while self.bump_if(token::BINOP(token::BITWISE_XOR)) {
-            let exp2 = self.parse_bitwise_and_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(ast::BO_BITWISE_XOR, exp, exp2)));
+    fn parse_bitwise_xor_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_bitwise_and_expression());
+        while if_ok!(self.bump_if(token::BINOP(token::BITWISE_XOR))) {
+            let exp2 = if_ok!(self.parse_bitwise_and_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(ast::BO_BITWISE_XOR, exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
 
-    fn parse_bitwise_or_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_bitwise_xor_expression();
-        while self.bump_if(token::BINOP(token::BITWISE_OR)) {
-            let exp2 = self.parse_bitwise_xor_expression();
-            exp = ast::ExprBinary(~self.new_node(ast::BinaryExpression::new(ast::BO_BITWISE_OR, exp, exp2)));
+    fn parse_bitwise_or_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_bitwise_xor_expression());
+        while if_ok!(self.bump_if(token::BINOP(token::BITWISE_OR))) {
+            let exp2 = if_ok!(self.parse_bitwise_xor_expression());
+            exp = ast::ExprBinary(~self.new_node(start, ast::BinaryExpression::new(ast::BO_BITWISE_OR, exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
 
 
     // ECMA 11.11 Binary Logical Operators
-    fn parse_logical_and_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_bitwise_or_expression();
-        while self.bump_if(token::AND) {
-            let exp2 = self.parse_bitwise_or_expression();
-            exp = ast::ExprLogical(~self.new_node(ast::LogicalExpression::new(ast::LO_OR, exp, exp2)));
+    fn parse_logical_and_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_bitwise_or_expression());
+        while if_ok!(self.bump_if(token::AND)) {
+            let exp2 = if_ok!(self.parse_bitwise_or_expression());
+            exp = ast::ExprLogical(~self.new_node(start, ast::LogicalExpression::new(ast::LO_AND, exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
     
-    fn parse_logical_or_expression(&mut self) -> ast::Expression {
-        let mut exp = self.parse_logical_and_expression();
-        while self.bump_if(token::OR) {
-            let exp2 = self.parse_logical_and_expression();
-            exp = ast::ExprLogical(~self.new_node(ast::LogicalExpression::new(ast::LO_OR, exp, exp2)));
+    fn parse_logical_or_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let mut exp = if_ok!(self.parse_logical_and_expression());
+        while if_ok!(self.bump_if(token::OR)) {
+            let exp2 = if_ok!(self.parse_logical_and_expression());
+            exp = ast::ExprLogical(~self.new_node(start, ast::LogicalExpression::new(ast::LO_OR, exp, exp2)));
         }
-        exp
+        Ok(exp)
     }
 
     // ECMA 11.12 Conditional Operator ( ? : )
-    fn parse_conditional_expression(&mut self) {
-        fail!("Not Implemented");
+    fn parse_conditional_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let exp = if_ok!(self.parse_logical_or_expression());
+        if if_ok!(self.bump_if(token::HOOK)) {
+            let consequent = if_ok!(self.with_allow_in(true, |p| p.parse_assignment_expression()));
+            if_ok!(self.expect(token::COLON));
+            let alternate = if_ok!(self.parse_assignment_expression());
+            Ok(ast::ExprConditional(~self.new_node(start, ast::CondionalExpression::new(exp, consequent, alternate))))
+        } else {
+            Ok(exp)
+        }
     }
 
     // ECMA 11.13 Assignment Operators
-    fn parse_assignment_expression(&mut self) {
-        self.parse_conditional_expression();
-        fail!("Not Implemented")
+    fn parse_assignment_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let exp = if_ok!(self.parse_conditional_expression());
+        match self.token.tok {
+            token::ASSIGN
+            | token::BINOPEQ(_)
+            | token::OR_EQ
+            | token::AND_EQ
+            | token::NULLISH_EQ => (),
+            _ => return Ok(exp)
+        }
+        if !Parser::is_reference(&exp) {
+            return Err(self.error_at(InvalidLHSInAssignment, start));
+        }
+        let op = if_ok!(self.bump_curr());
+        let right = if_ok!(self.parse_assignment_expression());
+        Ok(ast::ExprAssignment(~self.new_node(start, ast::AssignmentExpression::new(util::token_to_assignment_operator(op), exp, right))))
+    }
+
+    // Only a reference can be assigned to. (ECMA 11.13.1)
+    fn is_reference(exp: &ast::Expression) -> bool {
+        match *exp {
+            ast::ExprIdentifier(_) | ast::ExprMember(_) => true,
+            _ => false
+        }
     }
 
     // ECMA 11.14 Comma Operator ( , )
-    fn parse_expression(&mut self) {
-        self.parse_assignment_expression();
-        fail!("Not Implemented");
+    fn parse_expression(&mut self) -> Result<ast::Expression, SyntaxError> {
+        let start = self.token.span.start;
+        let exp = if_ok!(self.parse_assignment_expression());
+        if !self.is_curr(token::COMMA) {
+            return Ok(exp);
+        }
+        let mut exps = ~[exp];
+        while if_ok!(self.bump_if(token::COMMA)) {
+            exps.push(if_ok!(self.parse_assignment_expression()));
+        }
+        Ok(ast::ExprSequence(~self.new_node(start, ast::SequenceExpression::new(exps))))
     }
 
 
     // ECMA 12 Statement
-    fn parse_statement(&mut self) {
+    fn parse_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        if self.is_curr_identifier() && *if_ok!(self.peek()) == token::COLON {
+            return self.parse_labelled_statement();
+        }
         // FIXME: remove copy.
-        let token = self.token.clone();
+        let token = self.token.tok.clone();
         match token {
             token::SEMICOLON => self.parse_empty_statement(),
-            token::LBRACE => self.parse_block(),
-            token::LPAREN => self.parse_expression_statement(),
-            token::IDENT(ref ident) => {
-                match ident.as_slice() {
-                    "if" => self.parse_if_statement(),
-                    "do" => self.parse_do_while_statement(),
-                    "while" => self.parse_while_statement(),
-                    "for" => self.parse_for_statement(),
-                    "continue" => self.parse_continue_statement(),
-                    "break" => self.parse_with_statement(),
-                    "return" => self.parse_return_statement(),
-                    "with" => self.parse_with_statement(),
-                    "switch" => self.parse_switch_statement(),
-                    "throw" => self.parse_throw_statement(),
-                    "try" => self.parse_try_statement(),
-                    "var" => self.parse_variable_statment(),
-                    _ => fail!("Not Implemented")
+            token::LBRACE => Ok(ast::StmtBlock(~if_ok!(self.parse_block()))),
+            token::KEYWORD(keyword) => {
+                match keyword {
+                    token::If => self.parse_if_statement(),
+                    token::Do => self.parse_do_while_statement(),
+                    token::While => self.parse_while_statement(),
+                    token::For => self.parse_for_statement(),
+                    token::Continue => self.parse_continue_statement(),
+                    token::Break => self.parse_break_statement(),
+                    token::Return => self.parse_return_statement(),
+                    token::With => self.parse_with_statement(),
+                    token::Switch => self.parse_switch_statement(),
+                    token::Throw => self.parse_throw_statement(),
+                    token::Try => self.parse_try_statement(),
+                    token::Var => self.parse_variable_statment(),
+                    token::Debugger => self.parse_debugger_statement(),
+                    token::Function => Err(self.error(NotImplemented)),
+                    // Keywords such as "this" and "new" start expressions.
+                    _ => self.parse_expression_statement(),
                 }
             }
-            _ => self.parse_expression(),
+            _ => self.parse_expression_statement(),
+        }
+    }
+
+    // Consume the semicolon ending a statement, or insert one by automatic
+    // semicolon insertion. (ECMA 7.9)
+    fn consume_semicolon(&mut self) -> Result<(), SyntaxError> {
+        if if_ok!(self.bump_if(token::SEMICOLON)) || self.can_insert_semicolon() {
+            Ok(())
+        } else {
+            Err(self.unexpected())
         }
     }
 
     // ECMA 12.1 Block
-    fn parse_block(&mut self) {
-        fail!("Not Implemented");
+    fn parse_block(&mut self) -> Result<Node<ast::BlockStatement>, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::LBRACE));
+        let body = if_ok!(self.parse_statmemt_list());
+        if_ok!(self.expect(token::RBRACE));
+        Ok(self.new_node(start, ast::BlockStatement::new(body)))
+    }
+
+    // ECMA 13 Function Definition
+    // The body of a function, with its braces. "return" is allowed in it,
+    // and "break" and "continue" do not reach the statements around it.
+    fn parse_function_body(&mut self) -> Result<Node<ast::BlockStatement>, SyntaxError> {
+        let in_function = replace(&mut self.in_function, true);
+        let in_iteration = replace(&mut self.in_iteration, false);
+        let in_switch = replace(&mut self.in_switch, false);
+        let labels = replace(&mut self.labels, ~[]);
+        let body = self.parse_block();
+        self.in_function = in_function;
+        self.in_iteration = in_iteration;
+        self.in_switch = in_switch;
+        self.labels = labels;
+        body
     }
 
-    fn parse_statmemt_list(&mut self) {
-        fail!("Not Implemented");
+    // The statements up to a "}" or the end of the source.
+    fn parse_statmemt_list(&mut self) -> Result<~[ast::Statement], SyntaxError> {
+        let mut stmts = ~[];
+        while !self.is_eof() && !self.is_curr(token::RBRACE) {
+            stmts.push(if_ok!(self.parse_statement()));
+        }
+        Ok(stmts)
     }
 
     // ECMA 12.2 Variable Statement
-    fn parse_variable_statment(&mut self) {
-        fail!("Not Implemented");
+    fn parse_variable_statment(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        let mut decl = if_ok!(self.parse_var_declarations());
+        if_ok!(self.consume_semicolon());
+        // The semicolon is a part of the declaration.
+        decl.loc = self.source_location(start);
+        decl.range = self.source_range(start);
+        Ok(ast::StmtDeclaration(~ast::DeclVariable(~decl)))
+    }
+
+    // "var" VariableDeclarationList, without the semicolon.
+    fn parse_var_declarations(&mut self) -> Result<Node<ast::VariableDeclaration>, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::Var)));
+        let kind = self.new_node(start, ast::Var);
+        let declarations = if_ok!(self.parse_variable_declaration_list());
+        Ok(self.new_node(start, ast::VariableDeclaration::new(declarations, kind)))
+    }
+
+    fn parse_variable_declaration_list(&mut self) -> Result<~[Node<ast::VariableDeclarator>], SyntaxError> {
+        let mut declarations = ~[if_ok!(self.parse_variable_declaration())];
+        while if_ok!(self.bump_if(token::COMMA)) {
+            declarations.push(if_ok!(self.parse_variable_declaration()));
+        }
+        Ok(declarations)
+    }
+
+    fn parse_variable_declaration(&mut self) -> Result<Node<ast::VariableDeclarator>, SyntaxError> {
+        let start = self.token.span.start;
+        let id = if_ok!(self.parse_identifier());
+        let init = if if_ok!(self.bump_if(token::ASSIGN)) {
+            Some(if_ok!(self.parse_assignment_expression()))
+        } else {
+            None
+        };
+        Ok(self.new_node(start, ast::VariableDeclarator::new(ast::PtrnIdentifier(~id), init)))
     }
 
     // ECMA 12.3 Empty Statement
-    fn parse_empty_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_empty_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::SEMICOLON));
+        Ok(ast::StmtEmpty(~self.new_node(start, ast::EmptyStatement)))
     }
 
     // ECMA 12.4 Expression Statement
-    fn parse_expression_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_expression_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        let exp = if_ok!(self.parse_expression());
+        if_ok!(self.consume_semicolon());
+        Ok(ast::StmtExpression(~self.new_node(start, ast::ExpressionStatement::new(exp))))
     }
 
     // ECMA 12.5 if Statement
-    fn parse_if_statement(&mut self) {
-        fail!("Not Implemented");
+    // An "else" belongs to the nearest "if".
+    fn parse_if_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::If)));
+        if_ok!(self.expect(token::LPAREN));
+        let test = if_ok!(self.parse_expression());
+        if_ok!(self.expect(token::RPAREN));
+        let consequent = if_ok!(self.parse_statement());
+        let alternate = if if_ok!(self.bump_if(token::KEYWORD(token::Else))) {
+            Some(if_ok!(self.parse_statement()))
+        } else {
+            None
+        };
+        Ok(ast::StmtIf(~self.new_node(start, ast::IfStatement::new(test, consequent, alternate))))
     }
 
     // ECMA 12.6 Iteration Statement
 
     // ECMA 12.6.1 do-while Statement
-    fn parse_do_while_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_do_while_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::Do)));
+        let body = if_ok!(self.with_in_iteration(true, |p| p.parse_statement()));
+        if_ok!(self.expect(token::KEYWORD(token::While)));
+        if_ok!(self.expect(token::LPAREN));
+        let test = if_ok!(self.parse_expression());
+        if_ok!(self.expect(token::RPAREN));
+        // The semicolon is optional even on the same line, as browsers and
+        // later editions allow.
+        if_ok!(self.bump_if(token::SEMICOLON));
+        Ok(ast::StmtDoWhile(~self.new_node(start, ast::DoWhileStatement::new(body, test))))
     }
 
     // ECMA 12.6.2 while Statement
-    fn parse_while_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_while_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::While)));
+        if_ok!(self.expect(token::LPAREN));
+        let test = if_ok!(self.parse_expression());
+        if_ok!(self.expect(token::RPAREN));
+        let body = if_ok!(self.with_in_iteration(true, |p| p.parse_statement()));
+        Ok(ast::StmtWhile(~self.new_node(start, ast::WhileStatement::new(test, body))))
     }
 
     // ECMA 12.6.3 for Statement
-    fn parse_for_statement(&mut self) {
-        fail!("Not Implemented");
+    // The initialiser is an ExpressionNoIn or a VariableDeclarationListNoIn,
+    // so that an "in" after it makes a for-in statement.
+    fn parse_for_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::For)));
+        if_ok!(self.expect(token::LPAREN));
+        let init = if self.is_curr(token::SEMICOLON) {
+            None
+        } else if self.is_curr(token::KEYWORD(token::Var)) {
+            let decl = if_ok!(self.with_allow_in(false, |p| p.parse_var_declarations()));
+            // Only a single declaration can be the left side of a for-in.
+            if decl.body.declarations.len() == 1 && self.is_curr(token::KEYWORD(token::In)) {
+                return self.parse_for_in_statement(start, Left(decl));
+            }
+            Some(Left(decl))
+        } else {
+            let exp = if_ok!(self.with_allow_in(false, |p| p.parse_expression()));
+            if self.is_curr(token::KEYWORD(token::In)) {
+                if !Parser::is_reference(&exp) {
+                    return Err(self.error(InvalidLHSInForIn));
+                }
+                return self.parse_for_in_statement(start, Right(exp));
+            }
+            Some(Right(exp))
+        };
+        if_ok!(self.expect(token::SEMICOLON));
+        let test = if self.is_curr(token::SEMICOLON) {
+            None
+        } else {
+            Some(if_ok!(self.parse_expression()))
+        };
+        if_ok!(self.expect(token::SEMICOLON));
+        let update = if self.is_curr(token::RPAREN) {
+            None
+        } else {
+            Some(if_ok!(self.parse_expression()))
+        };
+        if_ok!(self.expect(token::RPAREN));
+        let body = if_ok!(self.with_in_iteration(true, |p| p.parse_statement()));
+        Ok(ast::StmtFor(~self.new_node(start, ast::ForStatement::new(init, test, update, body))))
     }
 
     // ECMA 12.6.4 for-in Statement
-    fn parse_for_in_statement(&mut self) {
-        fail!("Not Implemented");
+    // The rest of the statement after "for (" and the left side.
+    fn parse_for_in_statement(&mut self, start: Location,
+                              left: Either<Node<ast::VariableDeclaration>, ast::Expression>) -> Result<ast::Statement, SyntaxError> {
+        if_ok!(self.expect(token::KEYWORD(token::In)));
+        let right = if_ok!(self.parse_expression());
+        if_ok!(self.expect(token::RPAREN));
+        let body = if_ok!(self.with_in_iteration(true, |p| p.parse_statement()));
+        Ok(ast::StmtForIn(~self.new_node(start, ast::ForInStatement::new(left, right, body))))
+    }
+
+    // The label of "break" and "continue". It must be on the same line
+    // (ECMA 7.9.1), and be the label of a statement around.
+    fn parse_label(&mut self) -> Result<Option<Node<ast::Identifier>>, SyntaxError> {
+        if self.is_curr_identifier() && !self.has_newline_before() {
+            let start = self.token.span.start;
+            let label = if_ok!(self.parse_identifier());
+            if !self.labels.contains(&label.body.name) {
+                return Err(self.error_at(UnknownLabel, start));
+            }
+            Ok(Some(label))
+        } else {
+            Ok(None)
+        }
     }
 
     // ECMA 12.7 continue Statement
-    fn parse_continue_statement(&mut self) {
-        fail!("Not Implemented");
+    // It must be in an iteration statement.
+    fn parse_continue_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if !self.in_iteration {
+            return Err(self.error(IllegalContinue));
+        }
+        if_ok!(self.expect(token::KEYWORD(token::Continue)));
+        let label = if_ok!(self.parse_label());
+        if_ok!(self.consume_semicolon());
+        Ok(ast::StmtContinue(~self.new_node(start, ast::ContinueStatement::new(label))))
     }
 
     // ECMA 12.8 break Statement
-    fn parse_break_statement(&mut self) {
-        fail!("Not Implemented");
+    // Without a label, it must be in an iteration or a switch statement.
+    fn parse_break_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::Break)));
+        let label = if_ok!(self.parse_label());
+        if label.is_none() && !self.in_iteration && !self.in_switch {
+            let tok = Some(token::KEYWORD(token::Break));
+            return Err(SyntaxError::new(IllegalBreak, LMT_None, start, tok));
+        }
+        if_ok!(self.consume_semicolon());
+        Ok(ast::StmtBreak(~self.new_node(start, ast::BreakStatement::new(label))))
     }
 
     // ECMA 12.9 return Statement
-    fn parse_return_statement(&mut self) {
-        fail!("Not Implemented");
+    // It must be in a function body. The argument must start on the same line.
+    // (ECMA 7.9.1)
+    fn parse_return_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if !self.in_function {
+            return Err(self.error(IllegalReturn));
+        }
+        if_ok!(self.expect(token::KEYWORD(token::Return)));
+        let argument = if self.is_curr(token::SEMICOLON) || self.can_insert_semicolon() {
+            None
+        } else {
+            Some(if_ok!(self.parse_expression()))
+        };
+        if_ok!(self.consume_semicolon());
+        Ok(ast::StmtReturn(~self.new_node(start, ast::ReturnStatement::new(argument))))
     }
 
     // ECMA 12.10 with Statement
-    fn parse_with_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_with_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        Err(self.error(NotImplemented))
     }
 
     // ECMA 12.11 switch Statement
-    fn parse_switch_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_switch_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        if_ok!(self.expect(token::KEYWORD(token::Switch)));
+        if_ok!(self.expect(token::LPAREN));
+        let discriminant = if_ok!(self.parse_expression());
+        if_ok!(self.expect(token::RPAREN));
+        let in_switch = self.in_switch;
+        self.in_switch = true;
+        let cases = self.parse_case_block();
+        self.in_switch = in_switch;
+        let cases = if_ok!(cases);
+        Ok(ast::StmtSwitch(~self.new_node(start, ast::SwitchStatement::new(discriminant, cases, false))))
+    }
+
+    // The clauses in braces. There is one default clause at most.
+    fn parse_case_block(&mut self) -> Result<~[Node<ast::SwitchCase>], SyntaxError> {
+        if_ok!(self.expect(token::LBRACE));
+        let mut cases = ~[];
+        let mut has_default = false;
+        while !if_ok!(self.bump_if(token::RBRACE)) {
+            let start = self.token.span.start;
+            let test = if if_ok!(self.bump_if(token::KEYWORD(token::Case))) {
+                Some(if_ok!(self.parse_expression()))
+            } else {
+                if has_default && self.is_curr(token::KEYWORD(token::Default)) {
+                    return Err(self.error(MultipleDefaultsInSwitch));
+                }
+                if_ok!(self.expect(token::KEYWORD(token::Default)));
+                has_default = true;
+                None
+            };
+            if_ok!(self.expect(token::COLON));
+            let mut consequent = ~[];
+            while !self.is_eof() && !self.is_curr_any([token::RBRACE,
+                                                        token::KEYWORD(token::Case),
+                                                        token::KEYWORD(token::Default)]) {
+                consequent.push(if_ok!(self.parse_statement()));
+            }
+            cases.push(self.new_node(start, ast::SwitchCase::new(test, consequent)));
+        }
+        Ok(cases)
     }
 
     // ECMA 12.12 Labelled Statement
-    fn parse_labelled_statement(&mut self) {
-        fail!("Not Implemented");
+    // A label is known in its statement, and is not declared again in it.
+    fn parse_labelled_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        let start = self.token.span.start;
+        let label = if_ok!(self.parse_identifier());
+        if self.labels.contains(&label.body.name) {
+            return Err(self.error_at(Redeclaration, start));
+        }
+        if_ok!(self.expect(token::COLON));
+        self.labels.push(label.body.name.clone());
+        let body = self.parse_statement();
+        self.labels.pop();
+        let body = if_ok!(body);
+        Ok(ast::StmtLabled(~self.new_node(start, ast::LabledStatement::new(label, body))))
     }
 
     // ECMA 12.13 throw Statement
-    fn parse_throw_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_throw_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        Err(self.error(NotImplemented))
     }
 
     // ECMA 12.14 try Statement
-    fn parse_try_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_try_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        Err(self.error(NotImplemented))
     }
 
     // ECMA 12.15 debugger Statement
-    fn parse_debugger_statement(&mut self) {
-        fail!("Not Implemented");
+    fn parse_debugger_statement(&mut self) -> Result<ast::Statement, SyntaxError> {
+        Err(self.error(NotImplemented))
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Parser;
+    use super::{Parser, ParserOptions};
+    use ast;
+    use error::{InvalidLHSInAssignment, InvalidLHSInForIn};
+    use error::{IllegalReturn, IllegalBreak, IllegalContinue, UnknownLabel, Redeclaration};
+    use error::MultipleDefaultsInSwitch;
+    use reader::Location;
+    use token;
 
     #[test]
     fn simple_test() {
-        let src = ~"3+4";
-        let mut parser = Parser::new(src);
-        parser.parse();
+        let src = "3+4";
+        let mut parser = Parser::new(src).unwrap();
+        assert!(parser.parse().is_ok());
+        assert_eq!(parser.program.body.body.len(), 1);
+    }
+
+    fn literal<'a>(parser: &mut Parser<'a>) -> ~ast::Node<ast::Literal> {
+        match parser.parse_primary_expression().unwrap() {
+            ast::ExprLiteral(lit) => lit,
+            _ => fail!("not a literal")
+        }
+    }
+
+    fn identifier<'a>(parser: &mut Parser<'a>) -> ast::Atom {
+        match parser.parse_primary_expression().unwrap() {
+            ast::ExprIdentifier(id) => id.body.name,
+            _ => fail!("not an identifier")
+        }
+    }
+
+    #[test]
+    fn primary_expression() {
+        let mut parser = Parser::new("this a implements impl\\u0065ments 'b' 1.5 true null").unwrap();
+        let a = parser.lexer.atoms_mut().intern("a");
+        let b = parser.lexer.atoms_mut().intern("b");
+        match parser.parse_primary_expression().unwrap() {
+            ast::ExprThis(_) => (),
+            _ => fail!()
+        }
+        assert_eq!(identifier(&mut parser), a);
+        // Reserved only in strict mode code.
+        let implements = identifier(&mut parser);
+        assert_eq!(parser.lexer.atoms().get(implements), "implements");
+        assert_eq!(identifier(&mut parser), implements);
+        match literal(&mut parser).body.value {
+            ast::LV_String(s) => assert_eq!(s, b),
+            _ => fail!()
+        }
+        match literal(&mut parser).body.value {
+            ast::LV_Number(n) => assert_eq!(n, 1.5),
+            _ => fail!()
+        }
+        match literal(&mut parser).body.value {
+            ast::LV_Boolean(v) => assert!(v),
+            _ => fail!()
+        }
+        match literal(&mut parser).body.value {
+            ast::LV_Null => (),
+            _ => fail!()
+        }
+        assert!(parser.is_eof());
+
+        let mut parser = Parser::new("/c/g").unwrap();
+        let lit = literal(&mut parser);
+        match lit.body.value {
+            ast::LV_RegExp(body, flags) => {
+                assert_eq!(parser.lexer.atoms().get(body), "c");
+                assert_eq!(parser.lexer.atoms().get(flags), "g");
+            }
+            _ => fail!()
+        }
+
+        assert!(Parser::new(")").unwrap().parse_primary_expression().is_err());
+    }
+
+    #[test]
+    fn array_literal() {
+        let mut parser = Parser::new("[, a, , b, ]").unwrap();
+        match parser.parse_primary_expression().unwrap() {
+            ast::ExprArray(array) => {
+                let elements = &array.body.elements;
+                assert_eq!(elements.len(), 4);
+                assert!(elements[0].is_none());
+                assert!(elements[1].is_some());
+                assert!(elements[2].is_none());
+                assert!(elements[3].is_some());
+            }
+            _ => fail!()
+        }
+        assert!(parser.is_eof());
+
+        assert!(Parser::new("[a b]").unwrap().parse_primary_expression().is_err());
+        assert!(Parser::new("[a,").unwrap().parse_primary_expression().is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn object_literal() {
+        let mut parser = Parser::new("{a: 1, 'b': c, 2: d, if: e, get: f,}").unwrap();
+        let props = match parser.parse_primary_expression().unwrap() {
+            ast::ExprObject(object) => object.body.properties,
+            _ => fail!()
+        };
+        assert!(parser.is_eof());
+        assert_eq!(props.len(), 5);
+        assert!(props[0].key.is_right());
+        assert!(props[1].key.is_left());
+        assert!(props[2].key.is_left());
+        // Reserved words are property names.
+        let if_ = parser.lexer.atoms_mut().intern("if");
+        match props[3].key {
+            Right(ref id) => assert_eq!(id.body.name, if_),
+            Left(_) => fail!()
+        }
+
+        // Accessors have functions as their values.
+        let mut parser = Parser::new("{get a() { return 1 }, set 'a'(v) { b = v }, set: 2}").unwrap();
+        let props = match parser.parse_primary_expression().unwrap() {
+            ast::ExprObject(object) => object.body.properties,
+            _ => fail!()
+        };
+        assert!(parser.is_eof());
+        assert_eq!(props.len(), 3);
+        match props[0].kind {
+            ast::Get => (),
+            _ => fail!()
+        }
+        match props[1].kind {
+            ast::Set => assert!(props[1].key.is_left()),
+            _ => fail!()
+        }
+        match props[2].kind {
+            ast::Init => (),
+            _ => fail!()
+        }
+        match props[1].value {
+            ast::ExprFunction(ref f) => {
+                assert_eq!(f.body.function.body.params.len(), 1);
+                match f.body.function.body.body {
+                    Left(ref block) => assert_eq!(block.body.body.len(), 1),
+                    Right(_) => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        for src in ["({get a(b) {}})", "({set a() {}})", "({set a(b, c) {}})", "({get a() b})",
+                    "while (a) ({get b() { break }})"].iter() {
+            assert!(Parser::new(*src).unwrap().parse().is_err());
+        }
+        assert!(Parser::new("{a: 1 b: 2}").unwrap().parse_primary_expression().is_err());
+        assert!(Parser::new("{(a): 1}").unwrap().parse_primary_expression().is_err());
+    }
+
+    // The callee or the object of a member, call or new expression.
+    fn callee<'a>(exp: &'a ast::Expression) -> &'a ast::Expression {
+        match *exp {
+            ast::ExprMember(ref member) => &member.body.object,
+            ast::ExprCall(ref call) => &call.body.callee,
+            ast::ExprNew(ref new) => &new.body.callee,
+            _ => fail!("no callee")
+        }
+    }
+
+    #[test]
+    fn member_expression() {
+        let mut parser = Parser::new("a.if[b + 1].c").unwrap();
+        let a = parser.lexer.atoms_mut().intern("a");
+        let c = parser.lexer.atoms_mut().intern("c");
+        let exp = parser.parse_left_hand_side_expression().unwrap();
+        assert!(parser.is_eof());
+        match exp {
+            ast::ExprMember(ref member) => {
+                assert!(!member.body.computed);
+                match member.body.property {
+                    Left(ref id) => assert_eq!(id.body.name, c),
+                    Right(_) => fail!()
+                }
+                assert_eq!(member.loc.end.column, 13);
+            }
+            _ => fail!()
+        }
+        match *callee(&exp) {
+            ast::ExprMember(ref member) => {
+                assert!(member.body.computed);
+                match member.body.property {
+                    Right(ast::ExprBinary(_)) => (),
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        match *callee(callee(callee(&exp))) {
+            ast::ExprIdentifier(ref id) => assert_eq!(id.body.name, a),
+            _ => fail!()
+        }
+
+        assert!(Parser::new("a.").unwrap().parse_left_hand_side_expression().is_err());
+        assert!(Parser::new("a.(b)").unwrap().parse_left_hand_side_expression().is_err());
+        assert!(Parser::new("a[b").unwrap().parse_left_hand_side_expression().is_err());
+    }
+
+    #[test]
+    fn call_expression() {
+        let mut parser = Parser::new("f()(a, b)[0]()").unwrap();
+        let exp = parser.parse_left_hand_side_expression().unwrap();
+        assert!(parser.is_eof());
+        match exp {
+            ast::ExprCall(ref call) => assert_eq!(call.body.arguments.len(), 0),
+            _ => fail!()
+        }
+        match *callee(callee(&exp)) {
+            ast::ExprCall(ref call) => assert_eq!(call.body.arguments.len(), 2),
+            _ => fail!()
+        }
+        match *callee(callee(callee(callee(&exp)))) {
+            ast::ExprIdentifier(_) => (),
+            _ => fail!()
+        }
+
+        assert!(Parser::new("f(a,)").unwrap().parse_left_hand_side_expression().is_err());
+        assert!(Parser::new("f(a b)").unwrap().parse_left_hand_side_expression().is_err());
+        assert!(Parser::new("f(").unwrap().parse_left_hand_side_expression().is_err());
+    }
+
+    #[test]
+    fn new_expression() {
+        // The call belongs to "new", and the second call to its result.
+        let mut parser = Parser::new("new a.b()()").unwrap();
+        let exp = parser.parse_left_hand_side_expression().unwrap();
+        assert!(parser.is_eof());
+        match exp {
+            ast::ExprCall(ref call) => {
+                match call.body.callee {
+                    ast::ExprNew(ref new) => {
+                        assert_eq!(new.body.arguments.len(), 0);
+                        assert_eq!(new.loc.end.column, 9);
+                    }
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        match *callee(callee(&exp)) {
+            ast::ExprMember(_) => (),
+            _ => fail!()
+        }
+
+        // Without arguments.
+        let mut parser = Parser::new("new new a(1).b").unwrap();
+        let exp = parser.parse_left_hand_side_expression().unwrap();
+        assert!(parser.is_eof());
+        match exp {
+            ast::ExprNew(ref new) => assert_eq!(new.body.arguments.len(), 0),
+            _ => fail!()
+        }
+        match *callee(&exp) {
+            ast::ExprMember(_) => (),
+            _ => fail!()
+        }
+        match *callee(callee(&exp)) {
+            ast::ExprNew(ref new) => assert_eq!(new.body.arguments.len(), 1),
+            _ => fail!()
+        }
+
+        assert!(Parser::new("new").unwrap().parse_left_hand_side_expression().is_err());
+    }
+
+    #[test]
+    fn conditional_expression() {
+        let mut parser = Parser::new("a && b ? c = 1 : d ? e : f").unwrap();
+        match parser.parse_expression().unwrap() {
+            ast::ExprConditional(cond) => {
+                match cond.body.test {
+                    ast::ExprLogical(ref logical) => {
+                        match logical.body.operator {
+                            ast::LO_AND => (),
+                            _ => fail!()
+                        }
+                    }
+                    _ => fail!()
+                }
+                match cond.body.consequent {
+                    ast::ExprAssignment(_) => (),
+                    _ => fail!()
+                }
+                match cond.body.alternate {
+                    ast::ExprConditional(_) => (),
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        assert!(parser.is_eof());
+
+        assert!(Parser::new("a ? b").unwrap().parse_expression().is_err());
+        assert!(Parser::new("a ? b, c : d").unwrap().parse_expression().is_err());
+    }
+
+    #[test]
+    fn assignment_expression() {
+        // Assignments are right associative.
+        let mut parser = Parser::new("a = b.c **= d").unwrap();
+        match parser.parse_expression().unwrap() {
+            ast::ExprAssignment(assign) => {
+                match assign.body.operator {
+                    ast::AO_ASSIGN => (),
+                    _ => fail!()
+                }
+                match assign.body.right {
+                    ast::ExprAssignment(ref right) => {
+                        match right.body.operator {
+                            ast::AO_EXP => (),
+                            _ => fail!()
+                        }
+                        match right.body.left {
+                            ast::ExprMember(_) => (),
+                            _ => fail!()
+                        }
+                    }
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        assert!(parser.is_eof());
+
+        for src in ["1 = 2", "a + b = c", "f() >>>= 1", "(a, b) = c", "this = a"].iter() {
+            let err = Parser::new(*src).unwrap().parse_expression().unwrap_err();
+            assert_eq!(err.msg, InvalidLHSInAssignment);
+        }
+        // The error is at the left side.
+        let err = Parser::new("a,\n  f() = 1").unwrap().parse_expression().unwrap_err();
+        assert_eq!(err.loc, Location::new(5, 1, 2));
+
+        // A parenthesized reference is still a reference.
+        assert!(Parser::new("(a) = 1").unwrap().parse_expression().is_ok());
+
+        // Logical assignments.
+        let mut parser = Parser::new("a ||= b &&= c ??= d").unwrap();
+        match parser.parse_expression().unwrap() {
+            ast::ExprAssignment(assign) => {
+                match assign.body.operator {
+                    ast::AO_OR => (),
+                    _ => fail!()
+                }
+                match assign.body.right {
+                    ast::ExprAssignment(ref right) => {
+                        match right.body.operator {
+                            ast::AO_AND => (),
+                            _ => fail!()
+                        }
+                        match right.body.right {
+                            ast::ExprAssignment(ref right) => {
+                                match right.body.operator {
+                                    ast::AO_NULLISH => (),
+                                    _ => fail!()
+                                }
+                            }
+                            _ => fail!()
+                        }
+                    }
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        assert!(parser.is_eof());
+        let err = Parser::new("a + b ||= c").unwrap().parse_expression().unwrap_err();
+        assert_eq!(err.msg, InvalidLHSInAssignment);
+    }
+
+    #[test]
+    fn sequence_expression() {
+        let mut parser = Parser::new("a, b = 1, c").unwrap();
+        match parser.parse_expression().unwrap() {
+            ast::ExprSequence(seq) => {
+                assert_eq!(seq.body.expression.len(), 3);
+                assert_eq!(seq.loc.end.column, 11);
+            }
+            _ => fail!()
+        }
+        assert!(parser.is_eof());
+
+        // Commas in arguments separate them.
+        match Parser::new("f(a, b)").unwrap().parse_expression().unwrap() {
+            ast::ExprCall(_) => (),
+            _ => fail!()
+        }
+        assert!(Parser::new("a,").unwrap().parse_expression().is_err());
+    }
+
+    #[test]
+    fn variable_statement() {
+        let mut parser = Parser::new("var a = 1, b;\nvar c").unwrap();
+        let b = parser.lexer.atoms_mut().intern("b");
+        assert!(parser.parse().is_ok());
+        let body = &parser.program.body.body;
+        assert_eq!(body.len(), 2);
+        match body[0] {
+            ast::StmtDeclaration(ref decl) => match **decl {
+                ast::DeclVariable(ref var) => {
+                    let declarations = &var.body.declarations;
+                    assert_eq!(declarations.len(), 2);
+                    assert!(declarations[0].body.init.is_some());
+                    assert!(declarations[1].body.init.is_none());
+                    match declarations[1].body.id {
+                        ast::PtrnIdentifier(ref id) => assert_eq!(id.body.name, b),
+                        _ => fail!()
+                    }
+                    // The semicolon is a part of the declaration.
+                    assert_eq!(var.loc.end.column, 13);
+                }
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+
+        for src in ["var;", "var a b", "var 1", "var a = 1 2", "var a,"].iter() {
+            assert!(Parser::new(*src).unwrap().parse().is_err());
+        }
+    }
+
+    #[test]
+    fn block_and_empty_statement() {
+        let mut parser = Parser::new("{ a; { } ; }\n;").unwrap();
+        assert!(parser.parse().is_ok());
+        let body = &parser.program.body.body;
+        assert_eq!(body.len(), 2);
+        match body[0] {
+            ast::StmtBlock(ref block) => assert_eq!(block.body.body.len(), 3),
+            _ => fail!()
+        }
+        match body[1] {
+            ast::StmtEmpty(_) => (),
+            _ => fail!()
+        }
+
+        assert!(Parser::new("{ a").unwrap().parse().is_err());
+        assert!(Parser::new("}").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn expression_statement() {
+        // Semicolons are inserted at line breaks and before "}".
+        let mut parser = Parser::new("a = 1\nthis.b()\n{ c }").unwrap();
+        assert!(parser.parse().is_ok());
+        let body = &parser.program.body.body;
+        assert_eq!(body.len(), 3);
+        match body[0] {
+            ast::StmtExpression(ref stmt) => match stmt.body.expression {
+                ast::ExprAssignment(_) => (),
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+        match body[1] {
+            ast::StmtExpression(ref stmt) => match stmt.body.expression {
+                ast::ExprCall(_) => (),
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+        match body[2] {
+            ast::StmtBlock(ref block) => assert_eq!(block.body.body.len(), 1),
+            _ => fail!()
+        }
+
+        assert!(Parser::new("a b").unwrap().parse().is_err());
+        assert!(Parser::new("a = 1 b").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn update_expression() {
+        match Parser::new("a++").unwrap().parse_expression().unwrap() {
+            ast::ExprUpdate(update) => assert!(!update.body.prefix),
+            _ => fail!()
+        }
+
+        // A "++" on the next line starts the next statement.
+        let mut parser = Parser::new("a\n++b").unwrap();
+        assert!(parser.parse().is_ok());
+        let body = &parser.program.body.body;
+        assert_eq!(body.len(), 2);
+        match body[0] {
+            ast::StmtExpression(ref stmt) => match stmt.body.expression {
+                ast::ExprIdentifier(_) => (),
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+        match body[1] {
+            ast::StmtExpression(ref stmt) => match stmt.body.expression {
+                ast::ExprUpdate(ref update) => assert!(update.body.prefix),
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+        assert!(Parser::new("a\n++").unwrap().parse().is_err());
+    }
+
+    fn statement(src: &str) -> ast::Statement {
+        let mut parser = Parser::new(src).unwrap();
+        let stmt = parser.parse_statement().unwrap();
+        assert!(parser.is_eof());
+        stmt
+    }
+
+    #[test]
+    fn if_statement() {
+        // The "else" belongs to the inner "if".
+        match statement("if (a) if (b) c; else d") {
+            ast::StmtIf(stmt) => {
+                assert!(stmt.body.alternate.is_none());
+                match stmt.body.consquent {
+                    ast::StmtIf(ref inner) => assert!(inner.body.alternate.is_some()),
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+
+        assert!(Parser::new("if a b").unwrap().parse().is_err());
+        assert!(Parser::new("if (a) else b").unwrap().parse().is_err());
+    }
+
+    // Check that the statement is "/re/.test(s)".
+    fn assert_regexp_test(parser: &Parser, stmt: &ast::Statement, pattern: &str) {
+        let call = match *stmt {
+            ast::StmtExpression(ref stmt) => match stmt.body.expression {
+                ast::ExprCall(ref call) => call,
+                _ => fail!()
+            },
+            _ => fail!()
+        };
+        match call.body.callee {
+            ast::ExprMember(ref member) => match member.body.object {
+                ast::ExprLiteral(ref lit) => match lit.body.value {
+                    ast::LV_RegExp(body, _) => assert_eq!(parser.lexer.atoms().get(body), pattern),
+                    _ => fail!()
+                },
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+    }
+
+    #[test]
+    fn regexp_after_paren_and_brace() {
+        // The lexer takes these '/' for divisions first.
+        let mut parser = Parser::new("if (x) /re/.test(s)").unwrap();
+        assert!(parser.parse().is_ok());
+        match parser.program.body.body[0] {
+            ast::StmtIf(ref stmt) => assert_regexp_test(&parser, &stmt.body.consquent, "re"),
+            _ => fail!()
+        }
+
+        let mut parser = Parser::new("{}\n/re/.test(s)").unwrap();
+        assert!(parser.parse().is_ok());
+        assert_eq!(parser.program.body.body.len(), 2);
+        assert_regexp_test(&parser, &parser.program.body.body[1], "re");
+
+        // What follows them is not lexed as after a division.
+        let mut parser = Parser::new("if (x) /\\d+/.test(s)").unwrap();
+        assert!(parser.parse().is_ok());
+        match parser.program.body.body[0] {
+            ast::StmtIf(ref stmt) => assert_regexp_test(&parser, &stmt.body.consquent, "\\d+"),
+            _ => fail!()
+        }
+        for &pattern in ["'", "`", "\\d"].iter() {
+            let src = "{}\n/" + pattern + "/.test(s)";
+            let mut parser = Parser::new(src.as_slice()).unwrap();
+            assert!(parser.parse().is_ok());
+            assert_regexp_test(&parser, &parser.program.body.body[1], pattern);
+        }
+
+        let mut parser = Parser::new("{} /=/g; (a) / b").unwrap();
+        assert!(parser.parse().is_ok());
+        match parser.program.body.body[1] {
+            ast::StmtExpression(ref stmt) => match stmt.body.expression {
+                ast::ExprLiteral(_) => (),
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+        match parser.program.body.body[2] {
+            ast::StmtExpression(ref stmt) => match stmt.body.expression {
+                ast::ExprBinary(_) => (),
+                _ => fail!()
+            },
+            _ => fail!()
+        }
+    }
+
+    #[test]
+    fn iteration_statement() {
+        match statement("while (a) b = 1") {
+            ast::StmtWhile(_) => (),
+            _ => fail!()
+        }
+
+        // The semicolon after a do-while can be left out.
+        let mut parser = Parser::new("do a; while (b) c").unwrap();
+        assert!(parser.parse().is_ok());
+        assert_eq!(parser.program.body.body.len(), 2);
+        match parser.program.body.body[0] {
+            ast::StmtDoWhile(_) => (),
+            _ => fail!()
+        }
+
+        match statement("for (;;) {}") {
+            ast::StmtFor(stmt) => {
+                assert!(stmt.body.init.is_none());
+                assert!(stmt.body.test.is_none());
+                assert!(stmt.body.update.is_none());
+            }
+            _ => fail!()
+        }
+        match statement("for (var i = 0, j; i < j; i++) ;") {
+            ast::StmtFor(stmt) => {
+                match stmt.body.init {
+                    Some(Left(ref decl)) => assert_eq!(decl.body.declarations.len(), 2),
+                    _ => fail!()
+                }
+                assert!(stmt.body.test.is_some());
+                assert!(stmt.body.update.is_some());
+            }
+            _ => fail!()
+        }
+        // "in" is an operator in parentheses and in the consequent.
+        match statement("for (a = (b in c), d ? e in f : g; ; ) ;") {
+            ast::StmtFor(stmt) => {
+                match stmt.body.init {
+                    Some(Right(ast::ExprSequence(_))) => (),
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+
+        for src in ["for (a; b)", "for (a b)", "for (var a, b in c);", "while (a"].iter() {
+            assert!(Parser::new(*src).unwrap().parse().is_err());
+        }
+    }
+
+    #[test]
+    fn for_in_statement() {
+        match statement("for (var a = 1 in b) ;") {
+            ast::StmtForIn(stmt) => {
+                match stmt.body.left {
+                    Left(ref decl) => assert!(decl.body.declarations[0].body.init.is_some()),
+                    Right(_) => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        match statement("for (a.b[c in d] in e, f) ;") {
+            ast::StmtForIn(stmt) => {
+                match stmt.body.left {
+                    Right(ast::ExprMember(_)) => (),
+                    _ => fail!()
+                }
+                match stmt.body.right {
+                    ast::ExprSequence(_) => (),
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+
+        for src in ["for (a + b in c);", "for (a = b in c);", "for (f() in c);"].iter() {
+            let err = Parser::new(*src).unwrap().parse().unwrap_err();
+            assert_eq!(err.msg, InvalidLHSInForIn);
+        }
+    }
+
+    #[test]
+    fn jump_statement() {
+        // No line terminator is allowed before a label.
+        let mut parser = Parser::new("c: while (a) { break\nc; continue c }").unwrap();
+        let c = parser.lexer.atoms_mut().intern("c");
+        assert!(parser.parse().is_ok());
+        let stmts = match parser.program.body.body[0] {
+            ast::StmtLabled(ref stmt) => match stmt.body.body {
+                ast::StmtWhile(ref stmt) => match stmt.body.body {
+                    ast::StmtBlock(ref block) => &block.body.body,
+                    _ => fail!()
+                },
+                _ => fail!()
+            },
+            _ => fail!()
+        };
+        assert_eq!(stmts.len(), 3);
+        match stmts[0] {
+            ast::StmtBreak(ref stmt) => assert!(stmt.body.lable.is_none()),
+            _ => fail!()
+        }
+        match stmts[2] {
+            ast::StmtContinue(ref stmt) => {
+                match stmt.body.lable {
+                    Some(ref label) => assert_eq!(label.body.name, c),
+                    None => fail!()
+                }
+            }
+            _ => fail!()
+        }
+
+        // No line terminator is allowed before an argument.
+        let stmts = getter_body("return a, b; return\n1");
+        assert_eq!(stmts.len(), 3);
+        match stmts[0] {
+            ast::StmtReturn(ref stmt) => {
+                match stmt.body.argument {
+                    Some(ast::ExprSequence(_)) => (),
+                    _ => fail!()
+                }
+            }
+            _ => fail!()
+        }
+        match stmts[1] {
+            ast::StmtReturn(ref stmt) => assert!(stmt.body.argument.is_none()),
+            _ => fail!()
+        }
+        assert!(Parser::new("({get f() { return a b }})").unwrap().parse().is_err());
+
+        // Only in a function body, in a loop, in a switch or in a statement
+        // with the label.
+        for &(src, msg) in [("return a, b", IllegalReturn),
+                            ("break", IllegalBreak),
+                            ("if (a) break;", IllegalBreak),
+                            ("while (a) {} break", IllegalBreak),
+                            ("continue", IllegalContinue),
+                            ("{ continue c }", IllegalContinue),
+                            ("for (a in b) {} continue", IllegalContinue),
+                            ("switch (a) { case 1: continue }", IllegalContinue),
+                            ("while (a) break foo;", UnknownLabel),
+                            ("c: {} while (a) continue c", UnknownLabel),
+                            ("a: while (b) ({get c() { break a }})", UnknownLabel),
+                            ("a: a: ;", Redeclaration)].iter() {
+            let err = Parser::new(src).unwrap().parse().unwrap_err();
+            assert_eq!(err.msg, msg);
+        }
+        for src in ["do break; while (a)", "for (;;) continue", "for (a in b) if (a) break",
+                    "while (a) { while (b) {} continue }", "a: { break a }",
+                    "a: b: while (c) continue a", "a: ; a: ;", "a: ({get b() { a: ; }})",
+                    "switch (a) { case 1: b; break; default: }"].iter() {
+            assert!(Parser::new(*src).unwrap().parse().is_ok());
+        }
+
+        assert!(Parser::new("while (a) break 1").unwrap().parse().is_err());
+    }
+
+    // The statements in the body of a getter.
+    fn getter_body(src: &str) -> ~[ast::Statement] {
+        let src = "{get f() {" + src + "}}";
+        let mut parser = Parser::new(src.as_slice()).unwrap();
+        let mut props = match parser.parse_primary_expression().unwrap() {
+            ast::ExprObject(object) => object.body.properties,
+            _ => fail!()
+        };
+        assert!(parser.is_eof());
+        match props.pop().value {
+            ast::ExprFunction(f) => match f.body.function.body.body {
+                Left(block) => block.body.body,
+                Right(_) => fail!()
+            },
+            _ => fail!()
+        }
+    }
+
+    #[test]
+    fn switch_statement() {
+        let cases = match statement("switch (a) { case 1: case 2: b; break; default: c }") {
+            ast::StmtSwitch(stmt) => stmt.body.cases,
+            _ => fail!()
+        };
+        assert_eq!(cases.len(), 3);
+        assert!(cases[0].body.test.is_some());
+        assert_eq!(cases[0].body.consequent.len(), 0);
+        assert_eq!(cases[1].body.consequent.len(), 2);
+        assert!(cases[2].body.test.is_none());
+        assert_eq!(cases[2].body.consequent.len(), 1);
+
+        let err = Parser::new("switch (a) { default: default: }").unwrap().parse().unwrap_err();
+        assert_eq!(err.msg, MultipleDefaultsInSwitch);
+        for src in ["switch (a) { b }", "switch (a) { case 1 }", "switch a {}"].iter() {
+            assert!(Parser::new(*src).unwrap().parse().is_err());
+        }
+    }
+
+    #[test]
+    fn parenthesized_expression() {
+        let mut parser = Parser::new("(a + (b))").unwrap();
+        match parser.parse_primary_expression().unwrap() {
+            ast::ExprBinary(_) => (),
+            _ => fail!()
+        }
+        assert!(parser.is_eof());
+        assert!(Parser::new("(a").unwrap().parse_primary_expression().is_err());
+    }
+
+    #[test]
+    fn node_location() {
+        let src = "a +\n  b c";
+        let mut parser = Parser::new_with_options(src, ParserOptions { range: true }).unwrap();
+        let start = parser.token.span.start;
+        parser.bump().unwrap();
+        parser.bump().unwrap();
+        parser.bump().unwrap();
+        let node = parser.new_node(start, ());
+        assert_eq!(node.loc.start.line, 1);
+        assert_eq!(node.loc.start.column, 0);
+        assert_eq!(node.loc.end.line, 2);
+        assert_eq!(node.loc.end.column, 3);
+        assert_eq!(node.range, Some((0, 7)));
+
+        let mut parser = Parser::new("a").unwrap();
+        let start = parser.token.span.start;
+        parser.bump().unwrap();
+        let node: ast::Node<()> = parser.new_node(start, ());
+        assert_eq!(node.range, None);
+    }
+
+    #[test]
+    fn utf16_column() {
+        // U+1D4B3 is two UTF-16 code units, as in source maps.
+        let src = "'\U0001d4b3';\na; '\U0001d4b3', b";
+        let mut parser = Parser::new(src).unwrap();
+        assert!(parser.parse().is_ok());
+        let body = &parser.program.body.body;
+        match body[0] {
+            ast::StmtExpression(ref stmt) => assert_eq!(stmt.loc.end.column, 5),
+            _ => fail!()
+        }
+        match body[2] {
+            ast::StmtExpression(ref stmt) => {
+                assert_eq!(stmt.loc.start.column, 3);
+                assert_eq!(stmt.loc.end.column, 10);
+            }
+            _ => fail!()
+        }
+        assert_eq!(parser.line_index().line_col(src.len()), (1, 9));
+    }
+
+    #[test]
+    fn syntax_error() {
+        let err = Parser::new("a\n'b").unwrap_err();
+        assert_eq!(err.loc.row, 1);
+        assert_eq!(err.token, None);
+    }
+
+    #[test]
+    fn checkpoint() {
+        let mut parser = Parser::new("a\n(b)").unwrap();
+        let a = parser.lexer.atoms_mut().intern("a");
+        let b = parser.lexer.atoms_mut().intern("b");
+        let cp = parser.checkpoint();
+        parser.bump().unwrap();
+        parser.bump().unwrap();
+        assert!(parser.is_curr(token::IDENT(b)));
+
+        parser.rewind(cp);
+        assert!(parser.is_curr(token::IDENT(a)));
+        assert!(*parser.peek().unwrap() == token::LPAREN);
+        parser.bump().unwrap();
+        assert!(parser.has_newline_before());
+        assert_eq!(parser.prev_end.idx, 1);
+    }
+}
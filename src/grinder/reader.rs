@@ -1,86 +1,161 @@
+use error::{SyntaxError, UnterminatedComment, LMT_Illegal};
+use line_index::LineIndex;
+use token;
 use util;
 
 static NIL: char = '\xff';
 
-pub struct Reader {
-    priv src: ~str,
-    priv len: uint,
-    priv pos: Option<Position>,
-    priv pos_n1: Option<Position>,
-    priv pos_n2: Option<Position>,
+// A cursor over a borrowed source. It walks the bytes of the source and
+// decodes UTF-8 only at non-ASCII bytes. Lines are found by the line index
+// as the locations of tokens are asked for, as far as the reader has come.
+pub struct Reader<'a> {
+    priv src: &'a str,
+    // The byte offset of the current character.
+    priv pos: uint,
+    priv lines: LineIndex<'a>,
 }
 
+// A saved position of a reader, to go back to.
 #[deriving(Clone)]
-struct Position {
-    ch: char,
+pub struct ReaderState {
+    priv pos: uint,
+}
+
+// A location in the source.
+// `idx` is the byte offset. `row` and `col` count from 0, and `col` is in
+// UTF-16 code units.
+#[deriving(Clone, Eq)]
+pub struct Location {
     idx: uint,
     row: uint,
     col: uint,
 }
 
-impl Reader {
-    #[inline]
-    pub fn new(src: ~str) -> Reader {
-        let len = src.len();
-        let pos = if len > 0 {
-            Some(Position::new(src.char_range_at(0).ch, 0, 0, 0))
-        } else {
-            None
-        };
-        let pos_n1 = Reader::next_position(src, pos);
-        let pos_n2 = Reader::next_position(src, pos_n1);
+// What is between two tokens.
+#[deriving(Clone)]
+pub struct Trivia {
+    // Whether a line terminator is passed, including the ones in multi-line
+    // comments. (ECMA 7.4)
+    newline: bool,
+    // The comments, if they are asked to be kept.
+    comments: ~[token::Comment],
+}
 
+// Check if the byte is an ASCII identifier part.
+#[inline]
+fn is_ascii_ident_continue(b: u8) -> bool {
+    (b >= 'a' as u8 && b <= 'z' as u8) || (b >= 'A' as u8 && b <= 'Z' as u8)
+        || (b >= '0' as u8 && b <= '9' as u8) || b == '$' as u8 || b == '_' as u8
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    pub fn new(src: &'a str) -> Reader<'a> {
+        let lines = LineIndex::new(src);
+        // A leading byte order mark is skipped with the first line.
         Reader {
             src: src,
-            len: len,
-            pos: pos,
-            pos_n1: pos_n1,
-            pos_n2: pos_n2,
+            pos: lines.line_start(0),
+            lines: lines,
         }
     }
 
     #[inline]
     pub fn nil() -> char { NIL }
 
+    // Save the current position.
+    #[inline]
+    pub fn state(&self) -> ReaderState {
+        ReaderState {
+            pos: self.pos,
+        }
+    }
+
+    // Go back to a position saved by `state`.
     #[inline]
-    pub fn curr_pos(&self) -> Position {
-        self.pos.clone().unwrap()
+    pub fn restore(&mut self, state: ReaderState) {
+        self.pos = state.pos;
+    }
+
+    // Move to a byte offset. It must be the start of a token read before.
+    #[inline]
+    pub fn seek(&mut self, idx: uint) {
+        self.pos = idx;
+    }
+
+    // Return the location of the current character, or the end of the
+    // source at EOF.
+    #[inline]
+    pub fn curr_loc(&mut self) -> Location {
+        self.lines.location(self.pos)
+    }
+
+    // The lines of the source.
+    #[inline]
+    pub fn line_index<'b>(&'b mut self) -> &'b mut LineIndex<'a> {
+        &mut self.lines
     }
 
     #[inline]
     pub fn curr_pos_idx(&self) -> uint {
-        self.pos.map_default(self.len, |pos| pos.idx)
+        self.pos
+    }
+
+    // Return the character at the byte offset, or NIL at the end.
+    #[inline]
+    fn char_at(&self, idx: uint) -> char {
+        if idx >= self.src.len() {
+            NIL
+        } else if self.src[idx] < 0x80 {
+            self.src[idx] as char
+        } else {
+            self.src.char_range_at(idx).ch
+        }
+    }
+
+    // Return the byte offset of the character following the one at `idx`.
+    #[inline]
+    fn following_idx(&self, idx: uint) -> uint {
+        if idx >= self.src.len() {
+            idx
+        } else if self.src[idx] < 0x80 {
+            idx + 1
+        } else {
+            self.src.char_range_at(idx).next
+        }
     }
 
     #[inline]
     pub fn curr(&self) -> char {
-        self.pos.map_default(Reader::nil(), |pos| pos.ch)
+        self.char_at(self.pos)
     }
     #[inline]
     pub fn next(&self) -> char {
-        self.pos_n1.map_default(Reader::nil(), |pos| pos.ch)
+        self.char_at(self.following_idx(self.pos))
     }
     #[inline]
     pub fn next_next(&self) -> char {
-        self.pos_n2.map_default(Reader::nil(), |pos| pos.ch)
+        self.char_at(self.following_idx(self.following_idx(self.pos)))
     }
 
     #[inline]
     pub fn is_eof(&self) -> bool {
-        self.pos.is_none()
+        self.pos >= self.src.len()
     }
 
     #[inline]
     pub fn is_curr(&self, ch: char) -> bool {
-        self.pos.map_default(false, |pos| pos.ch == ch)
+        !self.is_eof() && self.curr() == ch
     }
     #[inline]
     pub fn is_next(&self, ch: char) -> bool {
-        self.pos_n1.map_default(false, |pos| pos.ch == ch)
+        let idx = self.following_idx(self.pos);
+        idx < self.src.len() && self.char_at(idx) == ch
     }
     #[inline]
     pub fn is_next_next(&self, ch: char) -> bool {
-        self.pos_n2.map_default(false, |pos| pos.ch == ch)
+        let idx = self.following_idx(self.following_idx(self.pos));
+        idx < self.src.len() && self.char_at(idx) == ch
     }
 
     #[inline]
@@ -91,7 +166,7 @@ impl Reader {
     }
     #[inline]
     pub fn bump_if(&mut self, ch: char) -> bool {
-        if self.curr() == ch {
+        if self.is_curr(ch) {
             self.bump();
             true
         } else {
@@ -101,106 +176,181 @@ impl Reader {
 
     #[inline]
     pub fn bump(&mut self) {
-        if self.pos.is_some() {
-            self.pos = self.pos_n1;
-            self.pos_n1 = self.pos_n2;
-            self.pos_n2 = Reader::next_position(self.src, self.pos_n1);
+        if self.is_eof() {
+            return;
+        }
+        if self.src[self.pos] < 0x80 {
+            self.pos += 1;
+        } else {
+            self.bump_non_ascii();
         }
     }
 
     #[inline]
-    pub fn with_str_from<R>(&self, start: uint, f: &fn(s: &str) -> R) -> R {
-        self.with_str_from_to(start, self.curr_pos_idx(), f)
+    fn bump_non_ascii(&mut self) {
+        self.pos = self.src.char_range_at(self.pos).next;
     }
+
+    // Skip ASCII identifier parts. Other characters are left to the caller.
     #[inline]
-    pub fn with_str_from_to<R>(&self, start: uint, end: uint, f: &fn(s: &str) -> R) -> R {
-        f(self.src.slice(start, end))
+    pub fn skip_ascii_ident_continue(&mut self) {
+        let bytes = self.src.as_bytes();
+        while self.pos < bytes.len() && is_ascii_ident_continue(bytes[self.pos]) {
+            self.pos += 1;
+        }
     }
 
+    // Return the source from `start` up to the current position. The slice
+    // borrows the source, not the reader.
     #[inline]
-    fn next_position(src: &str, pos: Option<Position>) -> Option<Position> {
-        let len = src.len();
-        match pos {
-            Some(ref pos) => {
-                assert!(pos.idx < len);
-                let next_idx = src.char_range_at(pos.idx).next;
-                if next_idx < len {
-                    let next_ch = src.char_range_at(next_idx).ch;
-                    let mut next_pos = Position::new(next_ch, next_idx, pos.row, pos.col);
-                    next_pos.set_next(next_idx, util::is_newline(pos.ch));
-                    Some(next_pos)
-                } else {
-                    None
+    pub fn slice_from(&self, start: uint) -> &'a str {
+        self.slice(start, self.pos)
+    }
+    #[inline]
+    pub fn slice(&self, start: uint, end: uint) -> &'a str {
+        self.src.slice(start, end)
+    }
+
+    // Eat white spaces, line terminators and comments.
+    // Comments are collected if `keep_comments` is set. In `script` code a
+    // hashbang at the start and HTML-like comments are comments too.
+    pub fn consume_whitespace_and_comments(&mut self, keep_comments: bool,
+                                           script: bool) -> Result<Trivia, SyntaxError> {
+        let mut trivia = Trivia { newline: false, comments: ~[] };
+        let at_start = self.pos == self.lines.line_start(0);
+        if script && at_start && self.is_curr('#') && self.is_next('!') {
+            let start = self.pos;
+            let text = self.consume_line_comment(2);
+            if keep_comments {
+                trivia.comments.push(self.comment(token::HashbangComment, start, text));
+            }
+        }
+        while !self.is_eof() {
+            let b = self.src[self.pos];
+            let start = self.pos;
+            if b == ' ' as u8 || b == '\t' as u8 {
+                self.pos += 1;
+            } else if b == '\n' as u8 || b == '\r' as u8 {
+                trivia.newline = true;
+                self.bump();
+            } else if b == '/' as u8 && self.is_next('/') {
+                let text = self.consume_line_comment(2);
+                if keep_comments {
+                    trivia.comments.push(self.comment(token::LineComment, start, text));
+                }
+            } else if b == '/' as u8 && self.is_next('*') {
+                let (text, newline) = if_ok!(self.consume_block_comment());
+                if newline {
+                    trivia.newline = true;
+                }
+                if keep_comments {
+                    trivia.comments.push(self.comment(token::BlockComment, start, text));
+                }
+            } else if script && self.is_html_comment_start(trivia.newline || at_start) {
+                let len = if b == '<' as u8 { 4 } else { 3 };
+                let text = self.consume_line_comment(len);
+                if keep_comments {
+                    trivia.comments.push(self.comment(token::LineComment, start, text));
                 }
+            } else if b >= 0x80 && util::is_whitespace(self.curr()) {
+                self.bump_non_ascii();
+            } else if b >= 0x80 && util::is_newline(self.curr()) {
+                trivia.newline = true;
+                self.bump_non_ascii();
+            } else if b < 0x80 && util::is_whitespace(b as char) {
+                self.pos += 1;
+            } else {
+                break;
             }
-            None => None
         }
+        Ok(trivia)
     }
 
-    // Eat white spaces and comments.
-    pub fn consume_whitespace_and_comments(&mut self) {
-        while util::is_whitespace(self.curr()) {
-            self.bump();
+    // Check if a line terminator starts at the byte offset.
+    // U+2028 and U+2029 are E2 80 A8 and E2 80 A9 in UTF-8.
+    #[inline]
+    fn is_newline_at(&self, idx: uint) -> bool {
+        let bytes = self.src.as_bytes();
+        match bytes[idx] {
+            0x0A | 0x0D => true,
+            0xE2 => idx + 2 < bytes.len() && bytes[idx + 1] == 0x80
+                        && (bytes[idx + 2] == 0xA8 || bytes[idx + 2] == 0xA9),
+            _ => false
         }
-        self.consume_comment();
     }
-    // Eat comments.
-    pub fn consume_comment(&mut self) {
-        if self.is_curr('/') {
-            if self.is_next('/') {
-                self.bump();
-                self.bump();
-                while !self.is_eof() && !self.is_curr('\n') {
-                    self.bump();
-                }
-                self.consume_whitespace_and_comments();
-            } else if self.is_next('*') {
-                self.bump();
-                self.bump();
-                while !self.is_eof() {
-                    if self.is_curr('*') && self.is_next('/') {
-                        self.bump();
-                        self.bump();
-                        break;
-                    } else {
-                        self.bump();
-                    }
-                }
-                self.consume_whitespace_and_comments();
+
+    // Check if an HTML-like comment starts here. "<!--" starts one anywhere,
+    // "-->" only at the start of a line. (ECMA B.1.3)
+    fn is_html_comment_start(&self, at_line_start: bool) -> bool {
+        let rest = self.src.slice_from(self.pos);
+        rest.starts_with("<!--") || (at_line_start && rest.starts_with("-->"))
+    }
+
+    // Make a comment from `start` up to the current position. Comments are
+    // made only when they are kept, as locating them is not free.
+    fn comment(&mut self, kind: token::CommentKind, start: uint, text: (uint, uint)) -> token::Comment {
+        let span = token::Span::new(self.lines.location(start), self.curr_loc());
+        token::Comment::new(kind, text, span)
+    }
+
+    // Eat a single-line comment whose opening delimiter is `delim_len`
+    // bytes long. The line terminator is left. Return the byte range of the
+    // text.
+    fn consume_line_comment(&mut self, delim_len: uint) -> (uint, uint) {
+        let text_start = self.pos + delim_len;
+        let mut end = text_start;
+        while end < self.src.len() && !self.is_newline_at(end) {
+            end += 1;
+        }
+        self.pos = end;
+        (text_start, end)
+    }
+
+    // Eat a multi-line comment. Return the byte range of the text, and true
+    // if it contains a line terminator.
+    fn consume_block_comment(&mut self) -> Result<((uint, uint), bool), SyntaxError> {
+        let mut newline = false;
+        let len = self.src.len();
+        self.pos += 2;
+        let mut i = self.pos;
+        loop {
+            if i >= len {
+                self.pos = len;
+                return Err(SyntaxError::new(UnterminatedComment, LMT_Illegal, self.curr_loc(), None));
             }
+            let b = self.src[i];
+            if b == '*' as u8 && i + 1 < len && self.src[i + 1] == '/' as u8 {
+                let text_start = self.pos;
+                self.pos = i + 2;
+                return Ok(((text_start, i), newline));
+            }
+            if self.is_newline_at(i) {
+                newline = true;
+            }
+            i += 1;
         }
     }
 }
 
-impl Position {
+impl Location {
     #[inline]
-    pub fn new(ch: char, idx: uint, row: uint, col: uint) -> Position {
-        Position {
-            ch: ch,
+    pub fn new(idx: uint, row: uint, col: uint) -> Location {
+        Location {
             idx: idx,
             row: row,
             col: col,
         }
     }
-    #[inline]
-    pub fn set_next(&mut self, next_idx: uint, is_new_line: bool) {
-        self.idx = next_idx;
-        if is_new_line {
-            self.row += 1;
-            self.col = 0;
-        } else {
-            self.col += 1;
-        }
-    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Reader;
+    use super::{Reader, Location};
+    use token;
 
     #[test]
     fn t1() {
-        let src = ~"var a = 1; var b=2; a + b;";
+        let src = "var a = 1; var b=2; a + b;";
         let mut reader = Reader::new(src);
 
         assert_eq!(reader.curr(), 'v'); reader.bump();
@@ -231,4 +381,114 @@ mod test {
         assert_eq!(reader.curr(), ';'); reader.bump();
         assert_eq!(reader.curr(), Reader::nil());
     }
+
+    #[test]
+    fn location() {
+        let src = "a\nbc";
+        let mut reader = Reader::new(src);
+
+        assert_eq!(reader.curr_loc(), Location::new(0, 0, 0)); reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(1, 0, 1)); reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(2, 1, 0)); reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(3, 1, 1)); reader.bump();
+        assert!(reader.is_eof());
+        assert_eq!(reader.curr_loc(), Location::new(4, 1, 2)); reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(4, 1, 2));
+
+        let mut reader = Reader::new("");
+        assert_eq!(reader.curr_loc(), Location::new(0, 0, 0));
+    }
+
+    #[test]
+    fn whitespace() {
+        let src = "\t\x0B\x0C \xA0\uFEFF\u1680\u2000\u200A\u202F\u205F\u3000a";
+        let mut reader = Reader::new(src);
+        assert!(!reader.consume_whitespace_and_comments(false, true).unwrap().newline);
+        assert_eq!(reader.curr(), 'a');
+
+        // U+180E is not a white space since Unicode 6.3.
+        let mut reader = Reader::new("\u180Ea");
+        assert!(!reader.consume_whitespace_and_comments(false, true).unwrap().newline);
+        assert_eq!(reader.curr(), '\u180E');
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let mut reader = Reader::new("\uFEFFa");
+        assert_eq!(reader.curr(), 'a');
+        assert_eq!(reader.curr_loc(), Location::new(3, 0, 0));
+
+        let reader = Reader::new("\uFEFF");
+        assert!(reader.is_eof());
+    }
+
+    #[test]
+    fn unterminated_comment() {
+        let mut reader = Reader::new("/* a */ /* b");
+        let err = reader.consume_whitespace_and_comments(false, true).unwrap_err();
+        assert_eq!(err.loc, Location::new(12, 0, 12));
+    }
+
+    #[test]
+    fn non_ascii_location() {
+        let mut reader = Reader::new("\u00e9\u3042a/* \u2028 */b");
+        reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(2, 0, 1)); reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(5, 0, 2)); reader.bump();
+        assert!(reader.consume_whitespace_and_comments(false, true).unwrap().newline);
+        assert_eq!(reader.curr(), 'b');
+        assert_eq!(reader.curr_loc(), Location::new(15, 1, 3));
+    }
+
+    #[test]
+    fn crlf() {
+        let mut reader = Reader::new("a\r\nb\rc");
+        reader.bump();
+        reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(2, 0, 2)); reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(3, 1, 0)); reader.bump();
+        reader.bump();
+        assert_eq!(reader.curr_loc(), Location::new(5, 2, 0));
+    }
+
+    #[test]
+    fn hashbang() {
+        let src = "#!/usr/bin/env node\na";
+        let mut reader = Reader::new(src);
+        let trivia = reader.consume_whitespace_and_comments(true, true).unwrap();
+        assert_eq!(trivia.comments[0].kind, token::HashbangComment);
+        assert_eq!(trivia.comments[0].text(src), "/usr/bin/env node");
+        assert_eq!(reader.curr(), 'a');
+
+        // Only at the very start, and not in module code.
+        let mut reader = Reader::new(" #!a");
+        reader.consume_whitespace_and_comments(false, true).unwrap();
+        assert_eq!(reader.curr(), '#');
+        let mut reader = Reader::new("#!a");
+        reader.consume_whitespace_and_comments(false, false).unwrap();
+        assert_eq!(reader.curr(), '#');
+    }
+
+    #[test]
+    fn html_comment() {
+        let src = "<!-- a\n  --> b\n/*\n*/ --> c\nd";
+        let mut reader = Reader::new(src);
+        let trivia = reader.consume_whitespace_and_comments(true, true).unwrap();
+        assert_eq!(trivia.comments.len(), 4);
+        assert_eq!(trivia.comments[0].text(src), " a");
+        assert_eq!(trivia.comments[1].text(src), " b");
+        assert_eq!(trivia.comments[2].text(src), "\n");
+        assert_eq!(trivia.comments[3].text(src), " c");
+        assert_eq!(reader.curr(), 'd');
+
+        // "-->" is not a comment in the middle of a line.
+        let mut reader = Reader::new("a -->");
+        reader.bump();
+        reader.consume_whitespace_and_comments(false, true).unwrap();
+        assert_eq!(reader.curr(), '-');
+
+        let mut reader = Reader::new("<!--");
+        reader.consume_whitespace_and_comments(false, false).unwrap();
+        assert_eq!(reader.curr(), '<');
+    }
 }
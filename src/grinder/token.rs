@@ -1,3 +1,6 @@
+use ast::{Atom, AtomTable};
+use reader::Location;
+
 #[deriving(Clone, Eq, IterBytes)]
 pub enum Binop {
     LSH,
@@ -6,6 +9,7 @@ pub enum Binop {
     PLUS,
     MINUS,
     MUL,
+    EXP,
     DIV,
     MOD,
     BITWISE_AND,
@@ -30,17 +34,23 @@ pub enum Token {
     NOT,
     OR,
     AND,
+    NULLISH,
+    OR_EQ,
+    AND_EQ,
+    NULLISH_EQ,
     BITWISE_NOT,
     BINOP(Binop),
     BINOPEQ(Binop), 
 
     // Structural symbols.
-    NEWLINE,
     SEMICOLON,
     COMMA,
     HOOK,
     COLON,
     DOT,
+    QUESTION_DOT,
+    ELLIPSIS,
+    ARROW,
     LBRACKET,
     RBRACKET,
     LBRACE,
@@ -50,26 +60,76 @@ pub enum Token {
 
     // Literals
     LITERAL(Literal),
+    // A part of a template, with the cooked value and the raw source. The
+    // cooked value is None if the part has an invalid escape.
+    TEMPLATE(TemplatePart, Option<Atom>, Atom),
 
     // Idents
-    // TODO: Interning idents.
-    IDENT(~str),
+    IDENT(Atom),
+
+    // Reserved words
+    KEYWORD(Keyword),
 
     EOF,
 }
 
+#[deriving(Clone, Eq, IterBytes)]
+pub enum TemplatePart {
+    NoSubstitutionTemplate, // "`...`"
+    TemplateHead,           // "`...${"
+    TemplateMiddle,         // "}...${"
+    TemplateTail,           // "}...`"
+}
+
+// A range of the source, from `start` up to but not including `end`.
+#[deriving(Clone, Eq)]
+pub struct Span {
+    start: Location,
+    end: Location,
+}
+
+#[deriving(Clone, Eq)]
+pub enum CommentKind {
+    LineComment,     // "// ...", and "<!-- ..." or "--> ..." in scripts
+    BlockComment,    // "/* ... */"
+    HashbangComment, // "#! ..." at the start of a script
+}
+
+#[deriving(Clone, Eq)]
+pub struct Comment {
+    kind: CommentKind,
+    // The byte range of the text between the delimiters.
+    text_range: (uint, uint),
+    span: Span,
+}
+
+// A token with the information on the source around it.
+#[deriving(Clone, Eq)]
+pub struct TokenInfo {
+    tok: Token,
+    span: Span,
+    // The comments between the previous token and this. They are kept only
+    // when the lexer is asked to.
+    comments: ~[Comment],
+    // Whether a line terminator comes between the previous token and this.
+    // Automatic semicolon insertion and restricted productions depend on it.
+    // (ECMA 7.9.1)
+    newline_before: bool,
+}
+
 #[deriving(Clone, Eq, IterBytes)]
 pub enum Literal {
-    LIT_BOOL(bool),
-    // TODO: Need to be implemented more efficiently.
-    LIT_NUMERIC(~str),
-    // TODO: Interning string literals.
-    LIT_STRING(~str),
-    // TODO: Interning regular expression literals.
-    LIT_REGEXP(~str),
+    // The value and the raw source.
+    LIT_NUMERIC(f64, Atom),
+    // The cooked value and the raw source including the quotes.
+    LIT_STRING(Atom, Atom),
+    // The body and the flags.
+    LIT_REGEXP(Atom, Atom),
 }
 
-// TODO: Handling future reserved words. (7.6.1.2)
+// Reserved words. (ECMA 7.6.1)
+// Contextual words such as 'let', 'yield', 'of', 'get', 'set', 'static' and
+// 'async' are not reserved. They are lexed as IDENT.
 #[deriving(Clone, Eq, IterBytes)]
 pub enum Keyword {
     Break,
@@ -104,5 +164,214 @@ pub enum Keyword {
     Void,
     While,
     With,
+
+    // Future reserved words. (ECMA 7.6.1.2)
+    Class,
+    Enum,
+    Extends,
+    Super,
+
+    // Future reserved words only in strict mode code.
+    Implements,
+    Interface,
+    Package,
+    Private,
+    Protected,
+    Public,
+}
+
+impl Token {
+    // Check if the token is an identifier with the given name. This is how
+    // the parser looks for contextual words.
+    #[inline]
+    pub fn is_ident_named(&self, atoms: &AtomTable, name: &str) -> bool {
+        match *self {
+            IDENT(atom) => atoms.get(atom) == name,
+            _ => false
+        }
+    }
+}
+
+impl Span {
+    #[inline]
+    pub fn new(start: Location, end: Location) -> Span {
+        Span {
+            start: start,
+            end: end,
+        }
+    }
+}
+
+impl TokenInfo {
+    #[inline]
+    pub fn new(tok: Token, span: Span, comments: ~[Comment], newline_before: bool) -> TokenInfo {
+        TokenInfo {
+            tok: tok,
+            span: span,
+            comments: comments,
+            newline_before: newline_before,
+        }
+    }
+
+    // The token type in esprima's `tokens` output.
+    pub fn type_name(&self) -> &'static str {
+        match self.tok {
+            KEYWORD(True) | KEYWORD(False) => "Boolean",
+            KEYWORD(Null) => "Null",
+            LITERAL(LIT_NUMERIC(..)) => "Numeric",
+            LITERAL(LIT_STRING(..)) => "String",
+            LITERAL(LIT_REGEXP(..)) => "RegularExpression",
+            TEMPLATE(..) => "Template",
+            IDENT(_) => "Identifier",
+            // Words reserved only in strict mode are identifiers otherwise.
+            KEYWORD(ref keyword) if keyword.is_strict_reserved() => "Identifier",
+            KEYWORD(_) => "Keyword",
+            EOF => "EOF",
+            _ => "Punctuator",
+        }
+    }
+
+    // The source text of the token, as the value in esprima's `tokens`
+    // output. `src` must be the source the token is lexed from.
+    pub fn value<'a>(&self, src: &'a str) -> &'a str {
+        src.slice(self.span.start.idx, self.span.end.idx)
+    }
+}
+
+impl Comment {
+    #[inline]
+    pub fn new(kind: CommentKind, text_range: (uint, uint), span: Span) -> Comment {
+        Comment {
+            kind: kind,
+            text_range: text_range,
+            span: span,
+        }
+    }
+
+    // The text between the delimiters. `src` must be the source the comment
+    // is lexed from.
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        let (start, end) = self.text_range;
+        src.slice(start, end)
+    }
+
+    // The comment type in esprima's `tokens` output.
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            LineComment => "LineComment",
+            BlockComment => "BlockComment",
+            HashbangComment => "Hashbang",
+        }
+    }
+}
+
+impl Keyword {
+    // Check if the keyword is reserved only in strict mode code. Such words
+    // are plain identifiers in non-strict code.
+    #[inline]
+    pub fn is_strict_reserved(&self) -> bool {
+        match *self {
+            Implements | Interface | Package | Private | Protected | Public => true,
+            _ => false
+        }
+    }
+}
+
+impl FromStr for Keyword {
+    fn from_str(s: &str) -> Option<Keyword> {
+        match s {
+            "break" => Some(Break),
+            "case" => Some(Case),
+            "catch" => Some(Catch),
+            "const" => Some(Const),
+            "continue" => Some(Continue),
+            "debugger" => Some(Debugger),
+            "default" => Some(Default),
+            "delete" => Some(Delete),
+            "do" => Some(Do),
+            "else" => Some(Else),
+            "export" => Some(Export),
+            "false" => Some(False),
+            "finally" => Some(Finally),
+            "for" => Some(For),
+            "function" => Some(Function),
+            "if" => Some(If),
+            "import" => Some(Import),
+            "in" => Some(In),
+            "instanceof" => Some(Instanceof),
+            "new" => Some(New),
+            "null" => Some(Null),
+            "return" => Some(Return),
+            "switch" => Some(Switch),
+            "this" => Some(This),
+            "throw" => Some(Throw),
+            "true" => Some(True),
+            "try" => Some(Try),
+            "typeof" => Some(Typeof),
+            "var" => Some(Var),
+            "void" => Some(Void),
+            "while" => Some(While),
+            "with" => Some(With),
+            "class" => Some(Class),
+            "enum" => Some(Enum),
+            "extends" => Some(Extends),
+            "super" => Some(Super),
+            "implements" => Some(Implements),
+            "interface" => Some(Interface),
+            "package" => Some(Package),
+            "private" => Some(Private),
+            "protected" => Some(Protected),
+            "public" => Some(Public),
+            _ => None
+        }
+    }
 }
 
+impl ToStr for Keyword {
+    fn to_str(&self) -> ~str {
+        match *self {
+            Break => ~"break",
+            Case => ~"case",
+            Catch => ~"catch",
+            Const => ~"const",
+            Continue => ~"continue",
+            Debugger => ~"debugger",
+            Default => ~"default",
+            Delete => ~"delete",
+            Do => ~"do",
+            Else => ~"else",
+            Export => ~"export",
+            False => ~"false",
+            Finally => ~"finally",
+            For => ~"for",
+            Function => ~"function",
+            If => ~"if",
+            Import => ~"import",
+            In => ~"in",
+            Instanceof => ~"instanceof",
+            New => ~"new",
+            Null => ~"null",
+            Return => ~"return",
+            Switch => ~"switch",
+            This => ~"this",
+            Throw => ~"throw",
+            True => ~"true",
+            Try => ~"try",
+            Typeof => ~"typeof",
+            Var => ~"var",
+            Void => ~"void",
+            While => ~"while",
+            With => ~"with",
+            Class => ~"class",
+            Enum => ~"enum",
+            Extends => ~"extends",
+            Super => ~"super",
+            Implements => ~"implements",
+            Interface => ~"interface",
+            Package => ~"package",
+            Private => ~"private",
+            Protected => ~"protected",
+            Public => ~"public",
+        }
+    }
+}
@@ -2,14 +2,22 @@ use std::char;
 use ast;
 use token;
 
+// Check if the character is a white space. (ECMA 7.2)
+// Line terminators are not white spaces. See is_newline().
 #[inline(always)]
 pub fn is_whitespace(c: char) -> bool {
-    c == ' ' || c == '\t' || c == '\r' || c == '\n'
+    match c {
+        ' ' | '\t' | '\x0B' | '\x0C' | '\xA0' | '\uFEFF' => true,
+        // Other characters in the "Zs" category.
+        '\u1680' | '\u2000'..'\u200A' | '\u202F' | '\u205F' | '\u3000' => true,
+        _ => false
+    }
 }
 
+// Check if the character is a line terminator. (ECMA 7.3)
 #[inline(always)]
 pub fn is_newline(ch: char) -> bool {
-    ch == '\n' || ch == '\r'
+    ch == '\n' || ch == '\r' || ch == '\u2028' || ch == '\u2029'
 }
 
 #[inline(always)]
@@ -17,20 +25,42 @@ pub fn is_quote(ch: char) -> bool {
     ch == '\'' || ch == '"'
 }
 
-// TODO: TO make sure the followings comply with ECMA spec. (7.6)
+// Check if the character can start an identifier. (ECMA 7.6)
+// That is ID_Start, '$' or '_'.
 #[inline(always)]
 pub fn is_ident_start(ch: char) -> bool {
     (ch >= 'a' && ch <= 'z')
         || (ch >= 'A' && ch <= 'Z')
         || ch == '_'
         || ch == '$'
-        || (ch > '\x7f' && char::is_XID_continue(ch))
+        || (ch > '\x7f' && (char::is_XID_start(ch) || is_id_not_xid(ch)))
 }
 
+// Check if the character can continue an identifier. (ECMA 7.6)
+// That is ID_Continue, '$', '_', ZWNJ or ZWJ.
 #[inline(always)]
 pub fn is_ident_continue(ch: char) -> bool {
-    is_ident_start(ch)
+    (ch >= 'a' && ch <= 'z')
+        || (ch >= 'A' && ch <= 'Z')
         || (ch >= '0' && ch <= '9')
+        || ch == '_'
+        || ch == '$'
+        || (ch > '\x7f' && (char::is_XID_continue(ch) || is_id_not_xid(ch)
+                             || ch == '\u200C' || ch == '\u200D'))
+}
+
+// The XID properties are the ID properties closed under NFKC. A few
+// characters are in ID_Start and ID_Continue but not in their XID
+// counterparts. (UAX #31)
+fn is_id_not_xid(ch: char) -> bool {
+    match ch {
+        '\u037A' | '\u0E33' | '\u0EB3' | '\u309B' | '\u309C'
+            | '\uFC5E'..'\uFC63' | '\uFDFA' | '\uFDFB'
+            | '\uFE70' | '\uFE72' | '\uFE74' | '\uFE76'
+            | '\uFE78' | '\uFE7A' | '\uFE7C' | '\uFE7E'
+            | '\uFF9E' | '\uFF9F' => true,
+        _ => false
+    }
 }
 
 #[inline(always)]
@@ -38,6 +68,11 @@ pub fn is_dec_digit(ch: char) -> bool {
     ch >= '0' && ch <= '9'
 }
 
+#[inline(always)]
+pub fn is_oct_digit(ch: char) -> bool {
+    ch >= '0' && ch <= '7'
+}
+
 pub fn token_to_binary_operator(token: token::Token) -> ast::BinaryOperator {
     match token {
         token::EQ => ast::BO_EQ,
@@ -54,37 +89,49 @@ pub fn token_to_binary_operator(token: token::Token) -> ast::BinaryOperator {
         token::BINOP(token::PLUS) => ast::BO_PLUS,
         token::BINOP(token::MINUS) => ast::BO_MINUS,
         token::BINOP(token::MUL) => ast::BO_MUL,
+        token::BINOP(token::EXP) => ast::BO_EXP,
         token::BINOP(token::DIV) => ast::BO_DIV,
         token::BINOP(token::MOD) => ast::BO_MOD,
         token::BINOP(token::BITWISE_AND) => ast::BO_BITWISE_AND,
         token::BINOP(token::BITWISE_OR) => ast::BO_BITWISE_OR,
         token::BINOP(token::BITWISE_XOR) => ast::BO_BITWISE_XOR,
-        token::IDENT(ref ident) => {
-            match ident.as_slice() {
-                "in" => ast::BO_IN,
-                "instanceof" => ast::BO_INSTANCEOF,
-                ".." => ast::BO_DOTDOT,
-                _ => fail!("{:?} is not a binray operator", token)
-            }
-        }
+        token::KEYWORD(token::In) => ast::BO_IN,
+        token::KEYWORD(token::Instanceof) => ast::BO_INSTANCEOF,
         _ => fail!("{:?} is not a binary operator", token)
     }
 }
 
+pub fn token_to_assignment_operator(token: token::Token) -> ast::AssignmentOperator {
+    match token {
+        token::ASSIGN => ast::AO_ASSIGN,
+        token::BINOPEQ(token::PLUS) => ast::AO_PLUS,
+        token::BINOPEQ(token::MINUS) => ast::AO_MINUS,
+        token::BINOPEQ(token::MUL) => ast::AO_MUL,
+        token::BINOPEQ(token::EXP) => ast::AO_EXP,
+        token::BINOPEQ(token::DIV) => ast::AO_DIV,
+        token::BINOPEQ(token::MOD) => ast::AO_MOD,
+        token::BINOPEQ(token::LSH) => ast::AO_LSH,
+        token::BINOPEQ(token::RSH) => ast::AO_RSH,
+        token::BINOPEQ(token::URSH) => ast::AO_RUSH,
+        token::BINOPEQ(token::BITWISE_OR) => ast::AO_BITWISE_OR,
+        token::BINOPEQ(token::BITWISE_XOR) => ast::AO_BITWISE_XOR,
+        token::BINOPEQ(token::BITWISE_AND) => ast::AO_BITWISE_AND,
+        token::OR_EQ => ast::AO_OR,
+        token::AND_EQ => ast::AO_AND,
+        token::NULLISH_EQ => ast::AO_NULLISH,
+        _ => fail!("{:?} is not an assignment operator", token)
+    }
+}
+
 pub fn token_to_unary_operator(token: token::Token) -> ast::UnaryOperator {
     match token {
         token::BINOP(token::PLUS) => ast::UO_PLUS,
         token::BINOP(token::MINUS) => ast::UO_MINUS,
         token::NOT => ast::UO_NOT,
         token::BITWISE_NOT => ast::UO_BITWISE_NOT,
-        token::IDENT(ref ident) => {
-            match ident.as_slice() {
-                "void" => ast::UO_VOID,
-                "typeof" => ast::UO_TYPEOF,
-                "delete" => ast::UO_DELETE,
-                _ => fail!("{:?} is not a unary operator", token)
-            }
-        }
+        token::KEYWORD(token::Void) => ast::UO_VOID,
+        token::KEYWORD(token::Typeof) => ast::UO_TYPEOF,
+        token::KEYWORD(token::Delete) => ast::UO_DELETE,
         _ => fail!("{:?} is not a unary operator", token)
     }
 }